@@ -14,7 +14,7 @@ fn load_bpf(interface: &interface::Interface, bpf_program_path: &Path, xdp_flags
     let (_bpf_object, bpf_fd) = libbpf::bpf_prog_load(bpf_program_path, libbpf::BpfProgType::XDP)?;
     libbpf::bpf_set_link_xdp_fd(&interface, Some(&bpf_fd), xdp_flags)?;
     let info = libbpf::bpf_obj_get_info_by_fd(&bpf_fd)?;
-    println!("Success Loading\n XDP prog name: {}, id {} on device: {}", info.name()?, info.id(), interface.ifindex());
+    println!("Success Loading\n XDP prog name: {}, id {} on device: {}", info.name()?, info.id(), interface.name());
     
     Ok(())
 }
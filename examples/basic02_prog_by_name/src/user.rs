@@ -3,28 +3,48 @@
 // http://www.gnu.org/licenses/gpl-3.0.en.html
 // (c) Lorenzo Vannucci
 
-use rebpf::{libbpf, interface, error as rebpf_error};
+use rebpf::{libbpf, interface, error as rebpf_error, userspace::xdp};
 use clap::{Arg, App};
-use std::path::Path;
+use std::{fs, path::{Path, PathBuf}};
 
 const DEFAULT_FILENAME: &str = "kern.o";
 const DEFAULT_PROG_SEC: &str = "xdp_pass";
 const DEFAULT_DEV: &str = "wlan0";
 
+// The id of the program loaded on an interface is stored here, so that unloading
+// detaches it only if it is still the attached program.
+fn prog_id_path(interface: &interface::Interface) -> PathBuf {
+    std::env::temp_dir().join(format!("basic02_prog_by_name_{}.id", interface.name()))
+}
+
 fn load_bpf(interface: &interface::Interface, bpf_program_path: &Path, xdp_flags: libbpf::XdpFlags, program_name: &str) -> Result<(), rebpf_error::Error> {
     let (bpf_object, _bpf_fd) = libbpf::bpf_prog_load(bpf_program_path, libbpf::BpfProgType::XDP)?;
     let bpf_prog = libbpf::bpf_object__find_program_by_title(&bpf_object, program_name)?;
     let bpf_fd = libbpf::bpf_program__fd(&bpf_prog)?;
     libbpf::bpf_set_link_xdp_fd(&interface, Some(&bpf_fd), xdp_flags)?;
     let info = libbpf::bpf_obj_get_info_by_fd(&bpf_fd)?;
-    println!("Success Loading\n XDP prog name: {}, id {} on device: {}", info.name()?, info.id(), interface.ifindex());
+    fs::write(prog_id_path(interface), info.id().to_string())
+        .map_err(|e| rebpf_error::Error::Custom(format!("Cannot store the program id: {}", e)))?;
+    println!("Success Loading\n XDP prog name: {}, id {} on device: {}", info.name()?, info.id(), interface.name());
     
     Ok(())
 }
 
 fn unload_bpf(interface: &interface::Interface, xdp_flags: libbpf::XdpFlags) -> Result<(), rebpf_error::Error> {
-    libbpf::bpf_set_link_xdp_fd(&interface, None, xdp_flags)?;
-    println!("Success Unloading.");
+    let loaded_id: u32 = match fs::read_to_string(prog_id_path(interface)).ok().and_then(|id| id.trim().parse().ok()) {
+        Some(id) => id,
+        None => {
+            println!("No XDP program loaded by this example on device: {}", interface.name());
+            return Ok(());
+        }
+    };
+    let map_ids = match xdp::attached_prog_info(&interface, xdp_flags)? {
+        Some((info, map_ids)) if info.id() == loaded_id => map_ids,
+        _ => Vec::new(),
+    };
+    xdp::detach_prog(&interface, loaded_id, xdp_flags)?;
+    let _ = fs::remove_file(prog_id_path(interface));
+    println!("Success Unloading\n XDP prog id {}, map ids {:?}", loaded_id, map_ids);
 
     Ok(())
 }
@@ -35,7 +35,7 @@ fn load_bpf(
         prog_sec,
         info.name()?,
         info.id(),
-        interface.ifindex()
+        interface.name()
     );
 
     Ok(bpf_object)
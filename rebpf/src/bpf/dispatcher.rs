@@ -0,0 +1,113 @@
+//! This module contains the kernel side of the XDP dispatcher,
+//! see [`dispatcher`] for an overview.
+//!
+//! The root program and every component program must declare the dispatcher
+//! maps with the [`xdp_dispatcher_maps`] macro. The root program starts the
+//! chain with `XdpDispatcher::run` and each component ends with
+//! `XdpDispatcher::next`, i.e.:
+//!
+//! ```
+//! use rebpf::{
+//!     bpf::dispatcher::XdpDispatcher,
+//!     libbpf::{XdpAction, XdpMd},
+//!     rebpf_macro::sec,
+//!     xdp_dispatcher_maps,
+//! };
+//!
+//! xdp_dispatcher_maps!();
+//!
+//! #[sec("xdp_dispatcher")]
+//! pub fn root(ctx: &XdpMd) -> XdpAction {
+//!     XdpDispatcher::new(&xdp_disp_progs, &xdp_disp_slots, &xdp_disp_cursor).run(ctx)
+//! }
+//!
+//! #[sec("xdp_component")]
+//! pub fn component(ctx: &XdpMd) -> XdpAction {
+//!     let action = XdpAction::PASS;
+//!     XdpDispatcher::new(&xdp_disp_progs, &xdp_disp_slots, &xdp_disp_cursor).next(ctx, action)
+//! }
+//! ```
+//!
+//! [`dispatcher`]: ../../dispatcher/index.html
+//! [`xdp_dispatcher_maps`]: ../../macro.xdp_dispatcher_maps.html
+
+use crate::{
+    bpf::maps::{Array, LookupMut, PerCpuArray, ProgArray, TailCall},
+    dispatcher::DispatcherSlot,
+    libbpf::{XdpAction, XdpMd},
+};
+
+/// Declare the maps used by the XDP dispatcher, with the names expected by
+/// the userspace side.
+#[macro_export]
+macro_rules! xdp_dispatcher_maps {
+    () => {
+        #[$crate::rebpf_macro::sec("maps")]
+        pub static xdp_disp_progs: $crate::bpf::maps::ProgArray =
+            $crate::bpf::maps::ProgArray::new($crate::dispatcher::MAX_DISPATCHER_PROGS);
+
+        #[$crate::rebpf_macro::sec("maps")]
+        pub static xdp_disp_slots: $crate::bpf::maps::Array<$crate::dispatcher::DispatcherSlot> =
+            $crate::bpf::maps::Array::new($crate::dispatcher::MAX_DISPATCHER_PROGS);
+
+        #[$crate::rebpf_macro::sec("maps")]
+        pub static xdp_disp_cursor: $crate::bpf::maps::PerCpuArray<u32> =
+            $crate::bpf::maps::PerCpuArray::new(1);
+    };
+}
+
+pub struct XdpDispatcher<'a> {
+    progs: &'a ProgArray,
+    slots: &'a Array<DispatcherSlot>,
+    cursor: &'a PerCpuArray<u32>,
+}
+
+impl<'a> XdpDispatcher<'a> {
+    #[inline(always)]
+    pub fn new(
+        progs: &'a ProgArray,
+        slots: &'a Array<DispatcherSlot>,
+        cursor: &'a PerCpuArray<u32>,
+    ) -> Self {
+        XdpDispatcher {
+            progs,
+            slots,
+            cursor,
+        }
+    }
+
+    /// Start the chain calling the first component program.
+    /// Returns `XdpAction::PASS` if there is no component.
+    #[inline(always)]
+    pub fn run(&self, ctx: &XdpMd) -> XdpAction {
+        // A program runs to completion on a single CPU, tail calls included,
+        // so a per-CPU cursor is enough to keep track of the position in the chain.
+        match unsafe { self.cursor.lookup_mut(&0) } {
+            Some(cursor) => *cursor = 0,
+            None => return XdpAction::ABORTED,
+        }
+        self.progs.tail_call(ctx, 0);
+        XdpAction::PASS
+    }
+
+    /// Call the next component program if the configuration of the current
+    /// one allows it for `action`, otherwise return `action`.
+    /// Returns `action` as well at the end of the chain.
+    #[inline(always)]
+    pub fn next(&self, ctx: &XdpMd, action: XdpAction) -> XdpAction {
+        let cursor = match unsafe { self.cursor.lookup_mut(&0) } {
+            Some(cursor) => cursor,
+            None => return action,
+        };
+        let chain_call_actions = match unsafe { self.slots.lookup_mut(cursor) } {
+            Some(slot) => slot.chain_call_actions,
+            None => return action,
+        };
+        if chain_call_actions & (1 << action as u32) == 0 {
+            return action;
+        }
+        *cursor += 1;
+        self.progs.tail_call(ctx, *cursor);
+        action
+    }
+}
@@ -4,8 +4,16 @@
 
 use crate::{
     error::Result,
-    helpers::{bpf_map_lookup_elem, bpf_map_update_elem, bpf_redirect_map},
-    libbpf::{BpfMapDef, BpfMapType, BpfUpdateElemFlags, XdpAction},
+    helpers::{
+        bpf_map_lookup_elem, bpf_map_update_elem, bpf_msg_redirect_hash, bpf_msg_redirect_map,
+        bpf_redirect_map, bpf_sk_redirect_hash, bpf_sk_redirect_map, bpf_sk_select_reuseport,
+        bpf_tail_call,
+    },
+    libbpf::{
+        BpfMapDef, BpfMapType, BpfUpdateElemFlags, SkAction, SkBuff, SkMsgMd, SkRedirectFlags,
+        SkReuseportMd, XdpAction,
+    },
+    lpm::{LpmKey, LPM_TRIE_MAP_FLAGS},
 };
 
 /// This trait is implemented by all the map wrapper types, as
@@ -20,8 +28,8 @@ pub trait Update: Map {
     /// Update a value inside the map.
     ///
     /// This operation is considered as atomic.
-    fn update<'a>(
-        &'a mut self,
+    fn update(
+        &mut self,
         key: &Self::Key,
         value: &Self::Value,
         flags: BpfUpdateElemFlags,
@@ -40,7 +48,7 @@ macro_rules! map_new {
 
 macro_rules! map_def {
     ($(#[$outer:meta])*
-    struct $map_type:ident < $key:ty, $value:ty >: $type_const:expr) => {
+    struct $map_type:ident < $key:ident, $value:ident >: $type_const:expr) => {
         #[repr(transparent)]
         $(#[$outer])*
         pub struct $map_type<$key, $value> {
@@ -80,6 +88,31 @@ pub trait Redirect: Map {
     }
 }
 
+/// This trait represents the ability for a map to jump into another bpf program
+/// (tail call), replacing the current program.
+pub trait TailCall: Map {
+    /// Jump into the program stored at `index`, with `ctx` as context.
+    ///
+    /// This function only returns if the tail call failed, for instance
+    /// because there is no program at this index.
+    fn tail_call<C>(&self, ctx: &C, index: Self::Key);
+}
+
+/// This trait represents the ability for a map to redirect the packets of
+/// `SK_SKB` programs and the messages of `SK_MSG` programs to a socket.
+pub trait SkRedirect: Map {
+    fn redirect_skb(&self, skb: &SkBuff, key: &Self::Key, flags: SkRedirectFlags) -> SkAction;
+    fn redirect_msg(&self, msg: &SkMsgMd, key: &Self::Key, flags: SkRedirectFlags) -> SkAction;
+}
+
+/// This trait represents the ability for a map to select the socket of a
+/// reuseport group receiving the packet of a `SK_REUSEPORT` program.
+pub trait SelectReuseport: Map {
+    /// Select the socket at `key`. The packet is dropped if the selected
+    /// socket doesn't belong to the reuseport group the program is attached to.
+    fn select(&self, ctx: &SkReuseportMd, key: &Self::Key) -> Result<()>;
+}
+
 pub trait LookupMut: Map {
     /// Lookup the map content associated with the given key.
     ///
@@ -91,6 +124,7 @@ pub trait LookupMut: Map {
     ///
     /// CF https://prototype-kernel.readthedocs.io/en/latest/bpf/ebpf_maps.html#kernel-side-ebpf-program
     /// for reference.
+    #[allow(clippy::mut_from_ref)]
     unsafe fn lookup_mut<'a>(&'a self, key: &Self::Key) -> Option<&'a mut Self::Value>;
 }
 
@@ -211,6 +245,13 @@ map_def! {
 impl_map_lookup_mut!(Array<T>);
 impl_map_update!(Array<T>);
 
+map_def! {
+    /// A hash map, with arbitrary data as keys and content.
+    struct HashMap<K, V>: BpfMapType::HASH
+}
+impl_map_lookup_mut!(HashMap<K, V>);
+impl_map_update!(HashMap<K, V>);
+
 map_def! {
     /// This map represent a faster array maintained on a per-CPU basis.
     struct PerCpuArray<T>: BpfMapType::PERCPU_ARRAY
@@ -218,3 +259,173 @@ map_def! {
 
 impl_map_lookup_mut!(PerCpuArray<T>);
 impl_map_update!(PerCpuArray<T>);
+
+map_def! {
+    /// A map holding references to bpf programs of the same type, used to
+    /// chain them with tail calls. The userspace application must fill it
+    /// with program fds.
+    ///
+    /// Example :
+    ///
+    /// ```
+    /// use rebpf::bpf::maps::{ProgArray, TailCall};
+    /// use rebpf::libbpf::{XdpAction, XdpMd};
+    /// use rebpf_macro::sec;
+    ///
+    /// #[sec("maps")]
+    /// pub static jump_table: ProgArray = ProgArray::new(4);
+    ///
+    /// #[sec("xdp_jump")]
+    /// pub fn jump(ctx: &XdpMd) -> XdpAction {
+    ///     // Jump into the program stored in the slot 1, if any.
+    ///     jump_table.tail_call(ctx, 1);
+    ///     XdpAction::PASS
+    /// }
+    /// ```
+    struct ProgArray: BpfMapType::PROG_ARRAY
+}
+
+impl TailCall for ProgArray {
+    fn tail_call<C>(&self, ctx: &C, index: u32) {
+        bpf_tail_call(ctx, &self.def, index)
+    }
+}
+
+map_def! {
+    /// A map holding sockets by index, used to splice them from `SK_SKB`
+    /// and `SK_MSG` programs. The userspace application must fill it with
+    /// socket fds.
+    ///
+    /// Example :
+    ///
+    /// ```
+    /// use rebpf::bpf::maps::{SkRedirect, SockMap};
+    /// use rebpf::libbpf::{SkAction, SkBuff, SkRedirectFlags};
+    /// use rebpf_macro::sec;
+    ///
+    /// #[sec("maps")]
+    /// pub static sock_map: SockMap = SockMap::new(2);
+    ///
+    /// #[sec("sk_skb/stream_verdict")]
+    /// pub fn stream_verdict(skb: &SkBuff) -> SkAction {
+    ///     // Send everything received on a socket of the map through the socket 1.
+    ///     sock_map.redirect_skb(skb, &1, SkRedirectFlags::empty())
+    /// }
+    /// ```
+    struct SockMap: BpfMapType::SOCKMAP
+}
+
+impl SkRedirect for SockMap {
+    fn redirect_skb(&self, skb: &SkBuff, key: &u32, flags: SkRedirectFlags) -> SkAction {
+        bpf_sk_redirect_map(skb, &self.def, *key, flags)
+    }
+
+    fn redirect_msg(&self, msg: &SkMsgMd, key: &u32, flags: SkRedirectFlags) -> SkAction {
+        bpf_msg_redirect_map(msg, &self.def, *key, flags)
+    }
+}
+
+/// A map holding sockets by arbitrary keys, i.e. a connection 4-tuple,
+/// used like a `SockMap`.
+#[repr(transparent)]
+pub struct SockHash<K> {
+    def: BpfMapDef<K, u32>,
+}
+
+impl<K> SockHash<K> {
+    map_new! {SockHash<K>: BpfMapType::SOCKHASH}
+}
+
+impl<K> Map for SockHash<K> {
+    type Key = K;
+    type Value = u32;
+}
+
+impl<K> SkRedirect for SockHash<K> {
+    fn redirect_skb(&self, skb: &SkBuff, key: &K, flags: SkRedirectFlags) -> SkAction {
+        bpf_sk_redirect_hash(skb, &self.def, key, flags)
+    }
+
+    fn redirect_msg(&self, msg: &SkMsgMd, key: &K, flags: SkRedirectFlags) -> SkAction {
+        bpf_msg_redirect_hash(msg, &self.def, key, flags)
+    }
+}
+
+map_def! {
+    /// A map holding the sockets of a reuseport group, used to steer the
+    /// packets of `SK_REUSEPORT` programs. The userspace application must
+    /// fill it with socket fds.
+    ///
+    /// Example :
+    ///
+    /// ```
+    /// use rebpf::bpf::maps::{ReuseportSockArray, SelectReuseport};
+    /// use rebpf::libbpf::{SkAction, SkReuseportMd};
+    /// use rebpf_macro::sec;
+    ///
+    /// #[sec("maps")]
+    /// pub static workers: ReuseportSockArray = ReuseportSockArray::new(4);
+    ///
+    /// #[sec("sk_reuseport")]
+    /// pub fn steer(ctx: &SkReuseportMd) -> SkAction {
+    ///     // Keep every flow on the same worker socket.
+    ///     match workers.select(ctx, &(ctx.hash() % 4)) {
+    ///         Ok(()) => SkAction::PASS,
+    ///         Err(_) => SkAction::DROP,
+    ///     }
+    /// }
+    /// ```
+    struct ReuseportSockArray: BpfMapType::REUSEPORT_SOCKARRAY
+}
+
+impl SelectReuseport for ReuseportSockArray {
+    fn select(&self, ctx: &SkReuseportMd, key: &u32) -> Result<()> {
+        bpf_sk_select_reuseport(ctx, &self.def, key, 0)
+    }
+}
+
+/// A map matching the longest prefix of its keys, i.e. to route or filter IP
+/// addresses by subnet. The kernel requires it to be created without
+/// preallocation, which `new` takes care of.
+///
+/// Example :
+///
+/// ```
+/// use rebpf::bpf::maps::{LookupMut, LpmTrie};
+/// use rebpf::libbpf::{XdpAction, XdpMd};
+/// use rebpf::lpm::LpmKey;
+/// use rebpf_macro::sec;
+///
+/// // Actions by IPv4 subnet, filled by the userspace application.
+/// #[sec("maps")]
+/// pub static subnets: LpmTrie<[u8; 4], u32> = LpmTrie::new(1024);
+///
+/// #[sec("xdp_filter")]
+/// pub fn filter(ctx: &XdpMd) -> XdpAction {
+///     let source = [192, 168, 1, 42];
+///     match unsafe { subnets.lookup_mut(&LpmKey::exact(source)) } {
+///         Some(&mut 1) => XdpAction::DROP,
+///         _ => XdpAction::PASS,
+///     }
+/// }
+/// ```
+#[repr(transparent)]
+pub struct LpmTrie<T, V> {
+    def: BpfMapDef<LpmKey<T>, V>,
+}
+
+impl<T, V> LpmTrie<T, V> {
+    pub const fn new(max_entries: u32) -> LpmTrie<T, V> {
+        LpmTrie {
+            def: BpfMapDef::with_flags(BpfMapType::LPM_TRIE, max_entries, LPM_TRIE_MAP_FLAGS),
+        }
+    }
+}
+
+impl<T, V> Map for LpmTrie<T, V> {
+    type Key = LpmKey<T>;
+    type Value = V;
+}
+
+impl_map_lookup_mut!(LpmTrie<T, V>);
+impl_map_update!(LpmTrie<T, V>);
@@ -1,4 +1,7 @@
 //! This module contains high-level bpf api built on top of libbpf safe wrapper api.
 
+pub mod dispatcher;
 pub mod maps;
+#[cfg(target_arch = "x86_64")]
+pub mod usdt;
 pub mod utils;
@@ -0,0 +1,127 @@
+//! This module contains the kernel side of the USDT support,
+//! see [`usdt`] for an overview.
+//!
+//! Example :
+//!
+//! ```
+//! use rebpf::{bpf::usdt::UsdtArgs, libbpf::PtRegs, rebpf_macro::sec, usdt_specs_map};
+//!
+//! usdt_specs_map!();
+//!
+//! #[sec("uprobe/query_start")]
+//! pub fn query_start(ctx: &PtRegs) -> i32 {
+//!     let args = match UsdtArgs::new(ctx, &usdt_specs) {
+//!         Some(args) => args,
+//!         None => return 0,
+//!     };
+//!     let _first = args.arg(0);
+//!     0
+//! }
+//! ```
+//!
+//! [`usdt`]: ../../usdt/index.html
+
+use crate::{
+    bpf::maps::{HashMap, LookupMut},
+    helpers::bpf_probe_read,
+    libbpf::PtRegs,
+    usdt::{UsdtArgSpec, UsdtSpec, USDT_ARG_CONST, USDT_ARG_REG, USDT_ARG_REG_DEREF},
+};
+
+/// Declare the map holding the USDT probe specifications, with the name
+/// expected by the userspace side.
+#[macro_export]
+macro_rules! usdt_specs_map {
+    () => {
+        #[$crate::rebpf_macro::sec("maps")]
+        pub static usdt_specs: $crate::bpf::maps::HashMap<u64, $crate::usdt::UsdtSpec> =
+            $crate::bpf::maps::HashMap::new($crate::usdt::MAX_USDT_SPECS);
+    };
+}
+
+/// Accessor to the arguments of the USDT probe being executed.
+pub struct UsdtArgs<'a> {
+    ctx: &'a PtRegs,
+    spec: &'a UsdtSpec,
+}
+
+impl<'a> UsdtArgs<'a> {
+    /// Returns None if the address of the probe being executed is unknown
+    /// to `specs`.
+    #[inline(always)]
+    pub fn new(ctx: &'a PtRegs, specs: &'a HashMap<u64, UsdtSpec>) -> Option<UsdtArgs<'a>> {
+        let spec = unsafe { specs.lookup_mut(&ctx.ip()) }?;
+        Some(UsdtArgs { ctx, spec })
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.spec.nr_args
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.spec.nr_args == 0
+    }
+
+    /// Returns the `n`-th argument of the probe, sign or zero extended
+    /// depending on its specification.
+    #[inline(always)]
+    pub fn arg(&self, n: u32) -> Option<i64> {
+        if n >= self.spec.nr_args {
+            return None;
+        }
+        let arg_spec: &UsdtArgSpec = self.spec.args.get(n as usize)?;
+        let raw: u64 = match arg_spec.kind {
+            USDT_ARG_CONST => arg_spec.value as u64,
+            USDT_ARG_REG => self.reg(arg_spec.reg_offset)?,
+            USDT_ARG_REG_DEREF => {
+                let address = self.reg(arg_spec.reg_offset)?.wrapping_add(arg_spec.value as u64);
+                read_user(address, arg_spec.size)?
+            }
+            _ => return None,
+        };
+        let shift = 64 - 8 * arg_spec.size.min(8);
+        if arg_spec.signed != 0 {
+            Some(((raw << shift) as i64) >> shift)
+        } else {
+            Some(((raw << shift) >> shift) as i64)
+        }
+    }
+
+    /// Reads the register at `reg_offset` in `pt_regs`.
+    #[inline(always)]
+    fn reg(&self, reg_offset: u32) -> Option<u64> {
+        // The verifier only allows constant offsets accessing the context directly.
+        let mut value: u64 = 0;
+        let src = (self.ctx as *const PtRegs as *const u8).wrapping_add(reg_offset as usize);
+        bpf_probe_read(&mut value, src as *const u64).ok()?;
+        Some(value)
+    }
+}
+
+#[inline(always)]
+fn read_user(address: u64, size: u32) -> Option<u64> {
+    match size {
+        1 => {
+            let mut v: u8 = 0;
+            bpf_probe_read(&mut v, address as *const u8).ok()?;
+            Some(v as u64)
+        }
+        2 => {
+            let mut v: u16 = 0;
+            bpf_probe_read(&mut v, address as *const u16).ok()?;
+            Some(v as u64)
+        }
+        4 => {
+            let mut v: u32 = 0;
+            bpf_probe_read(&mut v, address as *const u32).ok()?;
+            Some(v as u64)
+        }
+        _ => {
+            let mut v: u64 = 0;
+            bpf_probe_read(&mut v, address as *const u64).ok()?;
+            Some(v)
+        }
+    }
+}
@@ -0,0 +1,30 @@
+//! This module contains the definitions shared by the kernel side
+//! ([`bpf::dispatcher`]) and the userspace side ([`userspace::dispatcher`])
+//! of the XDP dispatcher.
+//!
+//! The dispatcher allows to run several XDP programs on the same interface:
+//! a root program, attached to the interface, tail calls the component
+//! programs one after the other in priority order. Each component decides,
+//! through the action it returns, whether the chain continues.
+//!
+//! [`bpf::dispatcher`]: ../bpf/dispatcher/index.html
+//! [`userspace::dispatcher`]: ../userspace/dispatcher/index.html
+
+/// Maximum number of component programs of a dispatcher.
+pub const MAX_DISPATCHER_PROGS: u32 = 32;
+
+/// Name of the `ProgArray` map holding the component programs.
+pub const DISPATCHER_PROGS_MAP: &str = "xdp_disp_progs";
+/// Name of the `Array<DispatcherSlot>` map holding the component configurations.
+pub const DISPATCHER_SLOTS_MAP: &str = "xdp_disp_slots";
+/// Name of the `PerCpuArray<u32>` map holding the position in the chain.
+pub const DISPATCHER_CURSOR_MAP: &str = "xdp_disp_cursor";
+
+/// Configuration of the component program stored in a dispatcher slot.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DispatcherSlot {
+    /// Bitmask of the XDP actions (bit `1 << action`) after which
+    /// the next component of the chain is called.
+    pub chain_call_actions: u32,
+}
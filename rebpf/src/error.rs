@@ -11,6 +11,12 @@ pub enum Error {
     InvalidProgName,
     #[error("Invalid BPF map name")]
     InvalidMapName,
+    #[error("Unexpected XDP program attached: expected id {0}, found {1:?}")]
+    UnexpectedXdpProg(u32, Option<u32>),
+    #[error("Key not found in the map")]
+    KeyNotFound,
+    #[error("System error: {0} ({1})")]
+    Sys(String, std::io::Error),
     #[error("Custom error: {0}")]
     Custom(String),
 }
@@ -2,7 +2,10 @@
 
 use crate::{
     error::{Error, LibbpfError},
-    libbpf::{BpfMapDef, BpfUpdateElemFlags, XdpAction},
+    libbpf::{
+        BpfMapDef, BpfUpdateElemFlags, SkAction, SkBuff, SkMsgMd, SkRedirectFlags, SkReuseportMd,
+        XdpAction,
+    },
     utils::*,
 };
 use libbpf_sys as libbpf;
@@ -16,10 +19,12 @@ use std::{
 ///
 /// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n649)
 /// for more details.
+// The value is owned by the kernel, which allows concurrent updates anyway.
+#[allow(clippy::mut_from_ref)]
 #[inline(always)]
-pub fn bpf_map_lookup_elem<'a, 'b, T, U>(
+pub fn bpf_map_lookup_elem<'a, T, U>(
     map: &'a BpfMapDef<T, U>,
-    key: &'b T,
+    key: &T,
 ) -> Option<&'a mut U> {
     type FPtrType = extern "C" fn(m: *const c_void, k: *const c_void) -> *mut c_void;
     unsafe {
@@ -39,9 +44,9 @@ pub fn bpf_map_lookup_elem<'a, 'b, T, U>(
 /// for more details.
 #[inline(always)]
 #[named]
-pub fn bpf_map_update_elem<'a, 'b, T, U>(
+pub fn bpf_map_update_elem<'a, T, U>(
     map: &'a mut BpfMapDef<T, U>,
-    key: &'b T,
+    key: &T,
     value: &'a U,
     flags: BpfUpdateElemFlags,
 ) -> Result<(), Error> {
@@ -69,9 +74,9 @@ pub fn bpf_map_update_elem<'a, 'b, T, U>(
 /// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n1626)
 /// for more details.
 #[inline(always)]
-pub fn bpf_redirect_map<'a, 'b, U>(
-    map: &'a BpfMapDef<u32, U>,
-    key: &'b u32,
+pub fn bpf_redirect_map<U>(
+    map: &BpfMapDef<u32, U>,
+    key: &u32,
     default_action: XdpAction,
 ) -> XdpAction {
     let flags: u32 = default_action as u32;
@@ -92,3 +97,174 @@ pub fn bpf_redirect_map<'a, 'b, U>(
         mem::transmute::<i32, XdpAction>(r)
     }
 }
+
+/// This function is a very thin wrapper around the built-in bpf_sk_redirect_map.
+/// It redirects the packet of a `SK_SKB` program to the socket at `key` in a `SockMap`.
+///
+/// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n1808)
+/// for more details.
+#[inline(always)]
+pub fn bpf_sk_redirect_map(
+    skb: &SkBuff,
+    map: &BpfMapDef<u32, u32>,
+    key: u32,
+    flags: SkRedirectFlags,
+) -> SkAction {
+    type FPtrType = extern "C" fn(s: *const c_void, m: *const c_void, k: u32, f: u64) -> c_int;
+    unsafe {
+        let f: FPtrType = mem::transmute(libbpf::BPF_FUNC_sk_redirect_map as usize);
+        let r = f(
+            to_const_c_void(skb),
+            to_const_c_void(&map.map_def),
+            key,
+            flags.bits(),
+        );
+        mem::transmute::<u32, SkAction>(r as u32)
+    }
+}
+
+/// This function is a very thin wrapper around the built-in bpf_sk_redirect_hash.
+/// It redirects the packet of a `SK_SKB` program to the socket at `key` in a `SockHash`.
+///
+/// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n2260)
+/// for more details.
+#[inline(always)]
+pub fn bpf_sk_redirect_hash<K>(
+    skb: &SkBuff,
+    map: &BpfMapDef<K, u32>,
+    key: &K,
+    flags: SkRedirectFlags,
+) -> SkAction {
+    type FPtrType =
+        extern "C" fn(s: *const c_void, m: *const c_void, k: *const c_void, f: u64) -> c_int;
+    unsafe {
+        let f: FPtrType = mem::transmute(libbpf::BPF_FUNC_sk_redirect_hash as usize);
+        let r = f(
+            to_const_c_void(skb),
+            to_const_c_void(&map.map_def),
+            to_const_c_void(key),
+            flags.bits(),
+        );
+        mem::transmute::<u32, SkAction>(r as u32)
+    }
+}
+
+/// This function is a very thin wrapper around the built-in bpf_msg_redirect_map.
+/// It redirects the message of a `SK_MSG` program to the socket at `key` in a `SockMap`.
+///
+/// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n1930)
+/// for more details.
+#[inline(always)]
+pub fn bpf_msg_redirect_map(
+    msg: &SkMsgMd,
+    map: &BpfMapDef<u32, u32>,
+    key: u32,
+    flags: SkRedirectFlags,
+) -> SkAction {
+    type FPtrType = extern "C" fn(s: *const c_void, m: *const c_void, k: u32, f: u64) -> c_int;
+    unsafe {
+        let f: FPtrType = mem::transmute(libbpf::BPF_FUNC_msg_redirect_map as usize);
+        let r = f(
+            to_const_c_void(msg),
+            to_const_c_void(&map.map_def),
+            key,
+            flags.bits(),
+        );
+        mem::transmute::<u32, SkAction>(r as u32)
+    }
+}
+
+/// This function is a very thin wrapper around the built-in bpf_msg_redirect_hash.
+/// It redirects the message of a `SK_MSG` program to the socket at `key` in a `SockHash`.
+///
+/// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n2237)
+/// for more details.
+#[inline(always)]
+pub fn bpf_msg_redirect_hash<K>(
+    msg: &SkMsgMd,
+    map: &BpfMapDef<K, u32>,
+    key: &K,
+    flags: SkRedirectFlags,
+) -> SkAction {
+    type FPtrType =
+        extern "C" fn(s: *const c_void, m: *const c_void, k: *const c_void, f: u64) -> c_int;
+    unsafe {
+        let f: FPtrType = mem::transmute(libbpf::BPF_FUNC_msg_redirect_hash as usize);
+        let r = f(
+            to_const_c_void(msg),
+            to_const_c_void(&map.map_def),
+            to_const_c_void(key),
+            flags.bits(),
+        );
+        mem::transmute::<u32, SkAction>(r as u32)
+    }
+}
+
+/// This function is a very thin wrapper around the built-in bpf_sk_select_reuseport.
+/// It selects the socket at `key` in a `ReuseportSockArray` to receive the packet
+/// of a `SK_REUSEPORT` program.
+///
+/// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n2067)
+/// for more details.
+#[inline(always)]
+#[named]
+pub fn bpf_sk_select_reuseport(
+    reuse: &SkReuseportMd,
+    map: &BpfMapDef<u32, u32>,
+    key: &u32,
+    flags: u64,
+) -> Result<(), Error> {
+    type FPtrType =
+        extern "C" fn(r: *const c_void, m: *const c_void, k: *const c_void, f: u64) -> c_int;
+    let r = unsafe {
+        let f: FPtrType = mem::transmute(libbpf::BPF_FUNC_sk_select_reuseport as usize);
+        f(
+            to_const_c_void(reuse),
+            to_const_c_void(&map.map_def),
+            to_const_c_void(key),
+            flags,
+        )
+    };
+    if r < 0 {
+        return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(r));
+    }
+    Ok(())
+}
+
+/// This function is a very thin wrapper around the built-in bpf_tail_call.
+/// It only returns if the tail call failed, for instance because there is no
+/// program at the given index of the map.
+///
+/// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n749)
+/// for more details.
+#[inline(always)]
+pub fn bpf_tail_call<C>(ctx: &C, map: &BpfMapDef<u32, u32>, index: u32) {
+    type FPtrType = extern "C" fn(c: *const c_void, m: *const c_void, i: u32) -> c_int;
+    unsafe {
+        let f: FPtrType = mem::transmute(libbpf::BPF_FUNC_tail_call as usize);
+        f(to_const_c_void(ctx), to_const_c_void(&map.map_def), index);
+    }
+}
+
+/// This function is a very thin wrapper around the built-in bpf_probe_read.
+/// It safely reads a value of type `T` at the (kernel or user) address `src`.
+///
+/// See the [kernel documentation](https://git.kernel.org/pub/scm/linux/kernel/git/torvalds/linux.git/tree/include/uapi/linux/bpf.h#n564)
+/// for more details.
+#[inline(always)]
+#[named]
+pub fn bpf_probe_read<T>(dst: &mut T, src: *const T) -> Result<(), Error> {
+    type FPtrType = extern "C" fn(d: *mut c_void, s: u32, u: *const c_void) -> c_int;
+    let r = unsafe {
+        let f: FPtrType = mem::transmute(libbpf::BPF_FUNC_probe_read as usize);
+        f(
+            to_mut_c_void(dst),
+            mem::size_of::<T>() as u32,
+            src as *const c_void,
+        )
+    };
+    if r < 0 {
+        return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(r));
+    }
+    Ok(())
+}
@@ -1,31 +1,354 @@
 use crate::{
-    error::{Error, GenericError},
+    error::{Error, GenericError, Result},
+    netlink::{self, IfInfoMsg, NetlinkMessage, NetlinkReply, NetlinkSocket},
+    netns::NetNs,
     utils,
 };
 use libc;
-use std::ffi::CString;
+use std::{ffi::CStr, mem, os::raw::c_char};
 
+const SIOCETHTOOL: libc::c_ulong = 0x8946;
+const ETHTOOL_GDRVINFO: u32 = 0x03;
+const ETHTOOL_GCHANNELS: u32 = 0x3c;
+
+/// `struct ethtool_drvinfo` from linux/ethtool.h.
+#[repr(C)]
+struct EthtoolDrvInfo {
+    cmd: u32,
+    driver: [c_char; 32],
+    version: [c_char; 32],
+    fw_version: [c_char; 32],
+    bus_info: [c_char; 32],
+    erom_version: [c_char; 32],
+    reserved2: [c_char; 12],
+    n_priv_flags: u32,
+    n_stats: u32,
+    testinfo_len: u32,
+    eedump_len: u32,
+    regdump_len: u32,
+}
+
+/// `struct ethtool_channels` from linux/ethtool.h.
+#[repr(C)]
+#[derive(Default)]
+struct EthtoolChannels {
+    cmd: u32,
+    max_rx: u32,
+    max_tx: u32,
+    max_other: u32,
+    max_combined: u32,
+    rx_count: u32,
+    tx_count: u32,
+    other_count: u32,
+    combined_count: u32,
+}
+
+/// `struct ifreq` with the `ifr_data` member of its union.
+#[repr(C)]
+struct IfReqData {
+    ifr_name: [c_char; libc::IFNAMSIZ],
+    ifr_data: *mut libc::c_void,
+    _pad: [u8; 16],
+}
+
+/// The driver of an interface, as reported by ethtool.
+#[derive(Debug, Clone)]
+pub struct DriverInfo {
+    pub driver: String,
+    pub version: String,
+    pub firmware_version: String,
+    pub bus_info: String,
+}
+
+/// The channels (queues) of an interface, as reported by ethtool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels {
+    pub max_rx: u32,
+    pub max_tx: u32,
+    pub max_combined: u32,
+    pub rx: u32,
+    pub tx: u32,
+    pub combined: u32,
+}
+
+fn c_chars_to_string(chars: &[c_char]) -> String {
+    if !chars.contains(&0) {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(chars.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A network interface, as reported by the kernel when it was looked up.
+///
+/// An interface looked up in a given network namespace remembers it: the
+/// programs attached to it are attached to the device of this namespace.
+#[derive(Debug, Clone)]
 pub struct Interface {
     pub(crate) ifindex: u32,
+    pub(crate) netns: Option<NetNs>,
+    pub(crate) name: String,
+    pub(crate) mac: Option<[u8; 6]>,
+    pub(crate) mtu: u32,
+    pub(crate) flags: u32,
+    pub(crate) num_rx_queues: u32,
+    pub(crate) num_tx_queues: u32,
 }
 
 impl Interface {
     pub fn ifindex(&self) -> u32 {
         self.ifindex
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The network namespace the interface was looked up in, None for the
+    /// namespace of the calling thread.
+    pub fn netns(&self) -> Option<&NetNs> {
+        self.netns.as_ref()
+    }
+
+    /// Run `f` in the network namespace of the interface.
+    pub(crate) fn run_in_netns<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match &self.netns {
+            Some(netns) => netns.run(f),
+            None => Ok(f()),
+        }
+    }
+
+    /// The MAC address of the interface, None if it has no ethernet address
+    /// (i.e. tun devices).
+    pub fn mac(&self) -> Option<[u8; 6]> {
+        self.mac
+    }
+
+    pub fn mtu(&self) -> u32 {
+        self.mtu
+    }
+
+    /// The `IFF_*` flags of the interface.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.flags & libc::IFF_UP as u32 != 0
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.flags & libc::IFF_LOOPBACK as u32 != 0
+    }
+
+    /// Returns true if the interface is backed by a device (i.e. a NIC),
+    /// as opposed to virtual interfaces such as bridges, veths or loopback.
+    ///
+    /// Only devices sitting on a bus report a bus address in their driver
+    /// info. sysfs can't be used: it shows the namespace of whoever mounted it.
+    pub fn is_physical(&self) -> bool {
+        match self.driver_info() {
+            Ok(info) => !matches!(info.bus_info.as_str(), "" | "N/A" | "tun"),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the driver of the interface (ETHTOOL_GDRVINFO).
+    #[named]
+    pub fn driver_info(&self) -> Result<DriverInfo> {
+        let mut drvinfo: EthtoolDrvInfo = unsafe { mem::zeroed() };
+        drvinfo.cmd = ETHTOOL_GDRVINFO;
+        let drvinfo = self.ethtool(function_name!(), drvinfo)?;
+        Ok(DriverInfo {
+            driver: c_chars_to_string(&drvinfo.driver),
+            version: c_chars_to_string(&drvinfo.version),
+            firmware_version: c_chars_to_string(&drvinfo.fw_version),
+            bus_info: c_chars_to_string(&drvinfo.bus_info),
+        })
+    }
+
+    /// Returns the channels of the interface (ETHTOOL_GCHANNELS), an error
+    /// with EOPNOTSUPP if the driver doesn't report them.
+    #[named]
+    pub fn channels(&self) -> Result<Channels> {
+        let channels = EthtoolChannels {
+            cmd: ETHTOOL_GCHANNELS,
+            ..Default::default()
+        };
+        let channels = self.ethtool(function_name!(), channels)?;
+        Ok(Channels {
+            max_rx: channels.max_rx,
+            max_tx: channels.max_tx,
+            max_combined: channels.max_combined,
+            rx: channels.rx_count,
+            tx: channels.tx_count,
+            combined: channels.combined_count,
+        })
+    }
+
+    /// Run the ethtool command `data` (starting with its command number),
+    /// and returns it as filled by the kernel.
+    fn ethtool<T: Send + 'static>(&self, function_name: &str, mut data: T) -> Result<T> {
+        let name = utils::str_to_cstring(&self.name)?;
+        if name.as_bytes_with_nul().len() > libc::IFNAMSIZ {
+            return utils::map_generic_error(GenericError::InvalidInterfaceName(
+                self.name.clone(),
+            ));
+        }
+        // The ioctl applies to the device of the namespace of the socket.
+        let reply = self.run_in_netns(move || {
+            let fd =
+                unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+            if fd < 0 {
+                return (std::io::Error::last_os_error().raw_os_error(), data);
+            }
+            let mut ifr = IfReqData {
+                ifr_name: [0; libc::IFNAMSIZ],
+                ifr_data: &mut data as *mut T as *mut libc::c_void,
+                _pad: [0; 16],
+            };
+            for (dst, src) in ifr.ifr_name.iter_mut().zip(name.as_bytes()) {
+                *dst = *src as c_char;
+            }
+            let err = unsafe { libc::ioctl(fd, SIOCETHTOOL, &mut ifr as *mut IfReqData) };
+            let errno = if err < 0 {
+                std::io::Error::last_os_error().raw_os_error()
+            } else {
+                None
+            };
+            unsafe { libc::close(fd) };
+            (errno, data)
+        })?;
+        match reply {
+            (Some(errno), _) => utils::map_errno_error(function_name, errno),
+            (None, data) => Ok(data),
+        }
+    }
+
+    pub fn num_rx_queues(&self) -> u32 {
+        self.num_rx_queues
+    }
+
+    pub fn num_tx_queues(&self) -> u32 {
+        self.num_tx_queues
+    }
+}
+
+/// Builds an `Interface` of the namespace `netns` from a RTM_NEWLINK message.
+pub(crate) fn parse_link(reply: &NetlinkReply, netns: Option<&NetNs>) -> Option<Interface> {
+    let (ifi, attrs) = reply.split::<IfInfoMsg>()?;
+    let mut interface = Interface {
+        ifindex: ifi.ifi_index as u32,
+        netns: netns.cloned(),
+        name: String::new(),
+        mac: None,
+        mtu: 0,
+        flags: ifi.ifi_flags,
+        num_rx_queues: 1,
+        num_tx_queues: 1,
+    };
+    for (attr_type, payload) in attrs {
+        match attr_type {
+            netlink::IFLA_IFNAME => interface.name = netlink::attr_str(payload)?,
+            netlink::IFLA_ADDRESS if payload.len() == 6 => {
+                let mut mac = [0u8; 6];
+                mac.copy_from_slice(payload);
+                interface.mac = Some(mac);
+            }
+            netlink::IFLA_MTU => interface.mtu = netlink::attr_u32(payload)?,
+            netlink::IFLA_NUM_RX_QUEUES => interface.num_rx_queues = netlink::attr_u32(payload)?,
+            netlink::IFLA_NUM_TX_QUEUES => interface.num_tx_queues = netlink::attr_u32(payload)?,
+            _ => {}
+        }
+    }
+    Some(interface)
 }
 
-fn if_nametoindex(dev: &str) -> Result<u32, Error> {
-    let dev_cstring: CString = utils::str_to_cstring(dev)?;
-    let ifindex = unsafe { libc::if_nametoindex(dev_cstring.as_ptr()) };
-    if ifindex == 0 {
-        utils::map_generic_error(GenericError::InvalidInterfaceName(dev.to_owned()))
-    } else {
-        Ok(ifindex)
+fn get_link(
+    function_name: &str,
+    netns: Option<&NetNs>,
+    ifi: &IfInfoMsg,
+    name: Option<&str>,
+) -> Result<Interface> {
+    let mut socket = NetlinkSocket::new_in(netns)?;
+    let mut msg = NetlinkMessage::new(netlink::RTM_GETLINK, 0, ifi);
+    if let Some(name) = name {
+        msg.push_attr_str(netlink::IFLA_IFNAME, name);
     }
+    let reply = socket.get(function_name, msg)?;
+    parse_link(&reply, netns).ok_or_else(|| Error::Custom("Invalid link message".to_owned()))
+}
+
+/// Look up the interface named `interface_name`.
+pub fn get_interface(interface_name: &str) -> Result<Interface> {
+    lookup_by_name(None, interface_name)
+}
+
+/// Look up the interface named `interface_name` in the network namespace `netns`.
+pub fn get_interface_in(netns: &NetNs, interface_name: &str) -> Result<Interface> {
+    lookup_by_name(Some(netns), interface_name)
+}
+
+#[named]
+fn lookup_by_name(netns: Option<&NetNs>, interface_name: &str) -> Result<Interface> {
+    match get_link(
+        function_name!(),
+        netns,
+        &IfInfoMsg::default(),
+        Some(interface_name),
+    ) {
+        Err(Error::Sys(_, ref e)) if e.raw_os_error() == Some(libc::ENODEV) => {
+            utils::map_generic_error(GenericError::InvalidInterfaceName(
+                interface_name.to_owned(),
+            ))
+        }
+        r => r,
+    }
+}
+
+/// Look up the interface whose index is `ifindex`.
+pub fn get_interface_by_index(ifindex: u32) -> Result<Interface> {
+    lookup_by_index(None, ifindex)
+}
+
+/// Look up the interface whose index is `ifindex` in the network namespace `netns`.
+pub fn get_interface_by_index_in(netns: &NetNs, ifindex: u32) -> Result<Interface> {
+    lookup_by_index(Some(netns), ifindex)
+}
+
+#[named]
+fn lookup_by_index(netns: Option<&NetNs>, ifindex: u32) -> Result<Interface> {
+    let ifi = IfInfoMsg {
+        ifi_index: ifindex as i32,
+        ..Default::default()
+    };
+    get_link(function_name!(), netns, &ifi, None)
+}
+
+/// List the interfaces, ordered by index.
+pub fn interfaces() -> Result<Vec<Interface>> {
+    list_links(None)
+}
+
+/// List the interfaces of the network namespace `netns`, ordered by index.
+pub fn interfaces_in(netns: &NetNs) -> Result<Vec<Interface>> {
+    list_links(Some(netns))
 }
 
-pub fn get_interface(interface_name: &str) -> Result<Interface, Error> {
-    let ifindex = if_nametoindex(interface_name)?;
-    Ok(Interface { ifindex })
+#[named]
+fn list_links(netns: Option<&NetNs>) -> Result<Vec<Interface>> {
+    let mut socket = NetlinkSocket::new_in(netns)?;
+    let msg = NetlinkMessage::new(netlink::RTM_GETLINK, 0, &IfInfoMsg::default());
+    let replies = socket.dump(function_name!(), msg)?;
+    let mut interfaces: Vec<Interface> = replies
+        .iter()
+        .filter_map(|reply| parse_link(reply, netns))
+        .collect();
+    interfaces.sort_by_key(|i| i.ifindex);
+    Ok(interfaces)
 }
@@ -19,6 +19,7 @@ extern crate function_name;
 
 pub use rebpf_macro;
 
+pub mod dispatcher;
 pub mod error;
 pub mod libbpf;
 
@@ -32,7 +33,16 @@ pub mod interface;
 #[cfg(feature = "userspace")]
 pub mod map_layout;
 #[cfg(feature = "userspace")]
+mod netlink;
+#[cfg(feature = "userspace")]
+pub mod netns;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "userspace")]
 pub mod userspace;
 
+pub mod lpm;
+pub mod usdt;
+
 pub const LICENSE: [u8; 4] = [b'G', b'P', b'L', b'\0']; //b"GPL\0"
 pub const VERSION: u32 = 0xFFFFFFFE;
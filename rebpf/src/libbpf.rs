@@ -5,7 +5,7 @@
 use crate::{
     error::{Error, LibbpfError, Result},
     interface,
-    map_layout::{MapLayout, PtrChecked, PtrCheckedMut},
+    map_layout::{BatchBuffer, BatchLayout, MapLayout, PtrChecked, PtrCheckedMut},
     utils::*,
 };
 
@@ -15,6 +15,7 @@ pub use libbpf_sys;
 use std::{ffi::CString, marker::PhantomData, mem, os::raw, path::Path, ptr};
 
 #[cfg(feature = "userspace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 #[allow(non_camel_case_types)]
 pub enum BpfProgType {
@@ -86,6 +87,64 @@ pub enum BpfMapType {
     DEVMAP_HASH = libbpf_sys::BPF_MAP_TYPE_DEVMAP_HASH,
 }
 
+#[cfg(feature = "userspace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+#[allow(non_camel_case_types)]
+pub enum BpfAttachType {
+    CGROUP_INET_INGRESS = libbpf_sys::BPF_CGROUP_INET_INGRESS,
+    CGROUP_INET_EGRESS = libbpf_sys::BPF_CGROUP_INET_EGRESS,
+    CGROUP_INET_SOCK_CREATE = libbpf_sys::BPF_CGROUP_INET_SOCK_CREATE,
+    CGROUP_SOCK_OPS = libbpf_sys::BPF_CGROUP_SOCK_OPS,
+    SK_SKB_STREAM_PARSER = libbpf_sys::BPF_SK_SKB_STREAM_PARSER,
+    SK_SKB_STREAM_VERDICT = libbpf_sys::BPF_SK_SKB_STREAM_VERDICT,
+    CGROUP_DEVICE = libbpf_sys::BPF_CGROUP_DEVICE,
+    SK_MSG_VERDICT = libbpf_sys::BPF_SK_MSG_VERDICT,
+    CGROUP_INET4_BIND = libbpf_sys::BPF_CGROUP_INET4_BIND,
+    CGROUP_INET6_BIND = libbpf_sys::BPF_CGROUP_INET6_BIND,
+    CGROUP_INET4_CONNECT = libbpf_sys::BPF_CGROUP_INET4_CONNECT,
+    CGROUP_INET6_CONNECT = libbpf_sys::BPF_CGROUP_INET6_CONNECT,
+    CGROUP_INET4_POST_BIND = libbpf_sys::BPF_CGROUP_INET4_POST_BIND,
+    CGROUP_INET6_POST_BIND = libbpf_sys::BPF_CGROUP_INET6_POST_BIND,
+    CGROUP_UDP4_SENDMSG = libbpf_sys::BPF_CGROUP_UDP4_SENDMSG,
+    CGROUP_UDP6_SENDMSG = libbpf_sys::BPF_CGROUP_UDP6_SENDMSG,
+    LIRC_MODE2 = libbpf_sys::BPF_LIRC_MODE2,
+    FLOW_DISSECTOR = libbpf_sys::BPF_FLOW_DISSECTOR,
+    CGROUP_SYSCTL = libbpf_sys::BPF_CGROUP_SYSCTL,
+    CGROUP_UDP4_RECVMSG = libbpf_sys::BPF_CGROUP_UDP4_RECVMSG,
+    CGROUP_UDP6_RECVMSG = libbpf_sys::BPF_CGROUP_UDP6_RECVMSG,
+    CGROUP_GETSOCKOPT = libbpf_sys::BPF_CGROUP_GETSOCKOPT,
+    CGROUP_SETSOCKOPT = libbpf_sys::BPF_CGROUP_SETSOCKOPT,
+}
+
+/// How a program attached to a cgroup combines with the programs attached
+/// to its descendants.
+#[cfg(feature = "userspace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+#[allow(non_camel_case_types)]
+pub enum BpfAttachFlags {
+    /// Only this program runs, descendants can't attach programs.
+    NONE = 0,
+    /// Descendants can replace this program by their own.
+    ALLOW_OVERRIDE = libbpf_sys::BPF_F_ALLOW_OVERRIDE,
+    /// Several programs can be attached, and the programs of the descendants
+    /// run before this one.
+    ALLOW_MULTI = libbpf_sys::BPF_F_ALLOW_MULTI,
+}
+
+#[cfg(feature = "userspace")]
+impl BpfAttachFlags {
+    pub fn from_u32(flags: u32) -> Option<BpfAttachFlags> {
+        match flags {
+            0 => Some(BpfAttachFlags::NONE),
+            libbpf_sys::BPF_F_ALLOW_OVERRIDE => Some(BpfAttachFlags::ALLOW_OVERRIDE),
+            libbpf_sys::BPF_F_ALLOW_MULTI => Some(BpfAttachFlags::ALLOW_MULTI),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(feature = "userspace")]
 pub struct BpfObject {
     pobj: *mut libbpf_sys::bpf_object,
@@ -99,7 +158,7 @@ pub struct BpfObjectIterator<'a> {
 
 #[cfg(feature = "userspace")]
 impl BpfObjectIterator<'_> {
-    fn new(src: &BpfObject) -> BpfObjectIterator {
+    fn new(src: &BpfObject) -> BpfObjectIterator<'_> {
         let next = bpf_program__next(None, src);
         BpfObjectIterator { src, next }
     }
@@ -167,6 +226,41 @@ where
     }
 }
 
+/// A fd owned by the caller, e.g. returned by `bpf_prog_get_fd_by_id`, closed
+/// on drop. Unlike the fds of the programs and maps of a `BpfObject`, which are
+/// closed by libbpf.
+#[cfg(feature = "userspace")]
+pub struct OwnedBpfFd<T: BpfFd> {
+    inner: T,
+}
+
+#[cfg(feature = "userspace")]
+impl<T: BpfFd> std::ops::Deref for OwnedBpfFd<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+#[cfg(feature = "userspace")]
+impl<T: BpfFd> BpfFd for OwnedBpfFd<T> {
+    type BpfInfoType = T::BpfInfoType;
+
+    fn fd(&self) -> raw::c_int {
+        self.inner.fd()
+    }
+}
+
+#[cfg(feature = "userspace")]
+impl<T: BpfFd> Drop for OwnedBpfFd<T> {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.inner.fd());
+        }
+    }
+}
+
 #[cfg(feature = "userspace")]
 pub struct BpfProgram {
     pprogram: *mut libbpf_sys::bpf_program,
@@ -196,6 +290,15 @@ impl BpfProgInfo {
         let name = &self.info.name;
         c_char_pointer_to_string(name.as_ptr())
     }
+
+    pub fn type_(&self) -> BpfProgType {
+        let prog_type: BpfProgType = unsafe { std::mem::transmute(self.info.type_) };
+        prog_type
+    }
+
+    pub fn nr_map_ids(&self) -> u32 {
+        self.info.nr_map_ids
+    }
 }
 
 #[cfg(feature = "userspace")]
@@ -247,13 +350,18 @@ pub struct BpfMapDef<T, U> {
 
 impl<T, U> BpfMapDef<T, U> {
     pub const fn new(type_: BpfMapType, max_entries: u32) -> Self {
+        Self::with_flags(type_, max_entries, 0)
+    }
+
+    /// Define a map created with the `BPF_F_*` flags `map_flags`.
+    pub const fn with_flags(type_: BpfMapType, max_entries: u32, map_flags: u32) -> Self {
         BpfMapDef {
             map_def: libbpf_sys::bpf_map_def {
                 type_: type_ as u32,
                 key_size: mem::size_of::<T>() as u32,
                 value_size: mem::size_of::<U>() as u32,
                 max_entries,
-                map_flags: 0,
+                map_flags,
             },
             _key_ty: PhantomData,
             _value_ty: PhantomData,
@@ -299,6 +407,9 @@ impl BpfMapInfo {
     pub fn max_entries(&self) -> u32 {
         self.info.max_entries
     }
+    pub fn map_flags(&self) -> u32 {
+        self.info.map_flags
+    }
     pub fn type_(&self) -> BpfMapType {
         let map_type: BpfMapType = unsafe { std::mem::transmute(self.info.type_) };
         map_type
@@ -336,6 +447,202 @@ where
     Ok(<<T as BpfFd>::BpfInfoType as BpfInfo>::new(info))
 }
 
+/// Returns the ids of the maps used by the program referred by `bpf_fd`.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_prog_get_map_ids(bpf_fd: &BpfProgFd) -> Result<Vec<u32>> {
+    let nr_map_ids = bpf_obj_get_info_by_fd(bpf_fd)?.nr_map_ids();
+    let mut map_ids: Vec<u32> = vec![0; nr_map_ids as usize];
+    let mut info: libbpf_sys::bpf_prog_info = unsafe { mem::zeroed() };
+    info.nr_map_ids = nr_map_ids;
+    info.map_ids = map_ids.as_mut_ptr() as u64;
+    let mut info_len: u32 = mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+    let err = unsafe {
+        libbpf_sys::bpf_obj_get_info_by_fd(bpf_fd.fd(), to_mut_c_void(&mut info), &mut info_len)
+    };
+    if err != 0 {
+        return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(err));
+    }
+    // The program may have been replaced by a program using less maps in the meantime.
+    map_ids.truncate(info.nr_map_ids.min(nr_map_ids) as usize);
+
+    Ok(map_ids)
+}
+
+/// A raw bpf instruction, laid out as `struct bpf_insn`.
+#[cfg(feature = "userspace")]
+#[repr(C)]
+pub(crate) struct RawBpfInsn {
+    pub(crate) code: u8,
+    /// The destination and source registers, one per nibble.
+    pub(crate) regs: u8,
+    pub(crate) off: i16,
+    pub(crate) imm: i32,
+}
+
+/// Thin wrapper around libbpf's bpf_load_program function, loading the GPL
+/// program made of `insns` without verifier log.
+#[cfg(feature = "userspace")]
+#[named]
+pub(crate) fn bpf_load_program(
+    prog_type: BpfProgType,
+    insns: &[RawBpfInsn],
+) -> Result<OwnedBpfFd<BpfProgFd>> {
+    let fd = unsafe {
+        libbpf_sys::bpf_load_program(
+            prog_type as u32,
+            insns.as_ptr() as *const libbpf_sys::bpf_insn,
+            insns.len() as _,
+            crate::LICENSE.as_ptr() as *const raw::c_char,
+            0,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if fd < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(OwnedBpfFd {
+        inner: BpfProgFd {
+            fd,
+            _info_type: std::marker::PhantomData,
+        },
+    })
+}
+
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_prog_get_fd_by_id(id: u32) -> Result<OwnedBpfFd<BpfProgFd>> {
+    let fd = unsafe { libbpf_sys::bpf_prog_get_fd_by_id(id) };
+    if fd < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(OwnedBpfFd {
+        inner: BpfProgFd {
+            fd,
+            _info_type: std::marker::PhantomData,
+        },
+    })
+}
+
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_map_get_fd_by_id(id: u32) -> Result<OwnedBpfFd<UnsafeBpfMapFd>> {
+    let fd = unsafe { libbpf_sys::bpf_map_get_fd_by_id(id) };
+    if fd < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(OwnedBpfFd {
+        inner: UnsafeBpfMapFd {
+            fd,
+            _info_type: std::marker::PhantomData,
+        },
+    })
+}
+
+/// Thin wrapper around libbpf's bpf_prog_attach function.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_prog_attach(
+    bpf_fd: &BpfProgFd,
+    target_fd: raw::c_int,
+    attach_type: BpfAttachType,
+    flags: BpfAttachFlags,
+) -> Result<()> {
+    let err = unsafe {
+        libbpf_sys::bpf_prog_attach(bpf_fd.fd(), target_fd, attach_type as u32, flags as u32)
+    };
+    if err < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(())
+}
+
+/// Thin wrapper around libbpf's bpf_prog_detach function, detaching the
+/// program attached to `target_fd` whatever it is.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_prog_detach(target_fd: raw::c_int, attach_type: BpfAttachType) -> Result<()> {
+    let err = unsafe { libbpf_sys::bpf_prog_detach(target_fd, attach_type as u32) };
+    if err < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(())
+}
+
+/// Thin wrapper around libbpf's bpf_prog_detach2 function.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_prog_detach2(
+    bpf_fd: &BpfProgFd,
+    target_fd: raw::c_int,
+    attach_type: BpfAttachType,
+) -> Result<()> {
+    let err = unsafe { libbpf_sys::bpf_prog_detach2(bpf_fd.fd(), target_fd, attach_type as u32) };
+    if err < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(())
+}
+
+/// Thin wrapper around libbpf's bpf_prog_query function.
+/// Returns the attach flags of `target_fd` and the ids of the attached programs.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_prog_query(
+    target_fd: raw::c_int,
+    attach_type: BpfAttachType,
+    query_flags: u32,
+) -> Result<(u32, Vec<u32>)> {
+    let mut attach_flags: u32 = 0;
+    let mut prog_cnt: u32 = 0;
+    let err = unsafe {
+        libbpf_sys::bpf_prog_query(
+            target_fd,
+            attach_type as u32,
+            query_flags,
+            &mut attach_flags,
+            ptr::null_mut(),
+            &mut prog_cnt,
+        )
+    };
+    if err < 0 {
+        return map_sys_error(function_name!());
+    }
+    let mut prog_ids: Vec<u32> = vec![0; prog_cnt as usize];
+    if prog_cnt > 0 {
+        let err = unsafe {
+            libbpf_sys::bpf_prog_query(
+                target_fd,
+                attach_type as u32,
+                query_flags,
+                &mut attach_flags,
+                prog_ids.as_mut_ptr(),
+                &mut prog_cnt,
+            )
+        };
+        if err < 0 {
+            return map_sys_error(function_name!());
+        }
+        // Programs may have been detached in the meantime.
+        prog_ids.truncate(prog_cnt as usize);
+    }
+    Ok((attach_flags, prog_ids))
+}
+
+/// Thin wrapper around libbpf's bpf_raw_tracepoint_open function.
+/// The program stays attached until the returned fd is closed.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_raw_tracepoint_open(name: &str, bpf_fd: &BpfProgFd) -> Result<raw::c_int> {
+    let name_cs = str_to_cstring(name)?;
+    let fd = unsafe { libbpf_sys::bpf_raw_tracepoint_open(name_cs.as_ptr(), bpf_fd.fd()) };
+    if fd < 0 {
+        return map_libbpf_sys_error(function_name!(), fd);
+    }
+    Ok(fd)
+}
+
 #[cfg(feature = "userspace")]
 #[named]
 pub fn bpf_prog_load(
@@ -366,6 +673,48 @@ pub fn bpf_prog_load(
     ))
 }
 
+#[cfg(feature = "userspace")]
+#[allow(non_snake_case)]
+#[named]
+pub fn bpf_object__open(file_path: &Path) -> Result<BpfObject> {
+    let file_path_s = path_to_str(file_path)?;
+    let file = str_to_cstring(file_path_s)?;
+    let pobj = unsafe { libbpf_sys::bpf_object__open(file.as_ptr()) };
+    let err = unsafe { libbpf_sys::libbpf_get_error(pobj as *const raw::c_void) };
+    if err != 0 {
+        return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(err as i32));
+    }
+
+    Ok(BpfObject { pobj })
+}
+
+#[cfg(feature = "userspace")]
+#[allow(non_snake_case)]
+#[named]
+pub fn bpf_object__load(bpf_object: &BpfObject) -> Result<()> {
+    let err = unsafe { libbpf_sys::bpf_object__load(bpf_object.pobj) };
+    if err != 0 {
+        return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(err));
+    }
+    Ok(())
+}
+
+/// Make `bpf_map` use the already existing map referred by `map_fd` instead of
+/// creating a new one when its object is loaded.
+#[cfg(feature = "userspace")]
+#[allow(non_snake_case)]
+#[named]
+pub fn bpf_map__reuse_fd<T: BpfFd<BpfInfoType = BpfMapInfo> + ?Sized>(
+    bpf_map: &BpfMap,
+    map_fd: &T,
+) -> Result<()> {
+    let err = unsafe { libbpf_sys::bpf_map__reuse_fd(bpf_map.pmap, map_fd.fd()) };
+    if err != 0 {
+        return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(err));
+    }
+    Ok(())
+}
+
 #[cfg(feature = "userspace")]
 pub fn bpf_map_lookup_elem<K, V, L: MapLayout<V>>(
     map_fd: &BpfMapFd<K, V, L>,
@@ -401,6 +750,243 @@ pub fn bpf_map_update_elem<K, V, L: MapLayout<V>>(
     }
 }
 
+/// Thin wrapper around libbpf's bpf_map_delete_elem function. Returns
+/// `Error::KeyNotFound` if the map has no entry for `key`.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_map_delete_elem<K, V, L: MapLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    key: &K,
+) -> Result<()> {
+    let key = to_const_c_void(key);
+    match unsafe { libbpf_sys::bpf_map_delete_elem(map_fd.fd(), key) } {
+        0 => Ok(()),
+        _ => map_map_elem_error(function_name!()),
+    }
+}
+
+/// Thin wrapper around libbpf's bpf_map_lookup_and_delete_elem function.
+/// Returns `Error::KeyNotFound` if the map has no entry for `key`.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_map_lookup_and_delete_elem<K, V, L: MapLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    key: &K,
+    value: &mut impl PtrCheckedMut<V, L>,
+) -> Result<()> {
+    let key = to_const_c_void(key);
+    match unsafe {
+        libbpf_sys::bpf_map_lookup_and_delete_elem(map_fd.fd(), key, value.ptr_checked_mut())
+    } {
+        0 => Ok(()),
+        _ => map_map_elem_error(function_name!()),
+    }
+}
+
+#[cfg(feature = "userspace")]
+type LookupBatchFn = unsafe extern "C" fn(
+    raw::c_int,
+    *mut raw::c_void,
+    *mut raw::c_void,
+    *mut raw::c_void,
+    *mut raw::c_void,
+    *mut u32,
+    *const libbpf_sys::bpf_map_batch_opts,
+) -> raw::c_int;
+
+/// Thin wrapper around libbpf's bpf_map_lookup_batch function (Linux 5.6+).
+///
+/// Reads at most `keys.len()` entries, starting at the position `in_batch` or
+/// at the beginning of the map if None, and stores the position of the next
+/// entries in `out_batch`. Returns the number of entries read and whether the
+/// end of the map was reached.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_map_lookup_batch<K, V, L: BatchLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    in_batch: Option<&u64>,
+    out_batch: &mut u64,
+    keys: &mut [maybe_uninit::MaybeUninit<K>],
+    values: &mut BatchBuffer<V, L>,
+) -> Result<(usize, bool)> {
+    lookup_batch(
+        function_name!(),
+        libbpf_sys::bpf_map_lookup_batch,
+        map_fd,
+        in_batch,
+        out_batch,
+        keys,
+        values,
+    )
+}
+
+/// Thin wrapper around libbpf's bpf_map_lookup_and_delete_batch function
+/// (Linux 5.6+), see `bpf_map_lookup_batch`.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_map_lookup_and_delete_batch<K, V, L: BatchLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    in_batch: Option<&u64>,
+    out_batch: &mut u64,
+    keys: &mut [maybe_uninit::MaybeUninit<K>],
+    values: &mut BatchBuffer<V, L>,
+) -> Result<(usize, bool)> {
+    lookup_batch(
+        function_name!(),
+        libbpf_sys::bpf_map_lookup_and_delete_batch,
+        map_fd,
+        in_batch,
+        out_batch,
+        keys,
+        values,
+    )
+}
+
+#[cfg(feature = "userspace")]
+fn lookup_batch<K, V, L: BatchLayout<V>>(
+    function_name: &str,
+    lookup: LookupBatchFn,
+    map_fd: &BpfMapFd<K, V, L>,
+    in_batch: Option<&u64>,
+    out_batch: &mut u64,
+    keys: &mut [maybe_uninit::MaybeUninit<K>],
+    values: &mut BatchBuffer<V, L>,
+) -> Result<(usize, bool)> {
+    if keys.len() != values.len() {
+        return Err(Error::Custom(format!(
+            "{}: {} keys for {} values",
+            function_name,
+            keys.len(),
+            values.len()
+        )));
+    }
+    let in_batch = in_batch.map_or(ptr::null_mut(), |b| to_const_c_void(b) as *mut _);
+    let mut count = keys.len() as u32;
+    let err = unsafe {
+        lookup(
+            map_fd.fd(),
+            in_batch,
+            to_mut_c_void(out_batch),
+            keys.as_mut_ptr() as *mut _,
+            values.as_mut_ptr(),
+            &mut count,
+            ptr::null(),
+        )
+    };
+    if err == 0 {
+        return Ok((count as usize, false));
+    }
+    let e = std::io::Error::last_os_error();
+    // The kernel reports the end of the map with ENOENT, after the last entries.
+    if e.raw_os_error() == Some(libc::ENOENT) {
+        return Ok((count as usize, true));
+    }
+    Err(Error::Sys(function_name.to_owned(), e))
+}
+
+/// Thin wrapper around libbpf's bpf_map_update_batch function (Linux 5.6+).
+/// On error, the entries before the failing one are updated.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_map_update_batch<K, V, L: BatchLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    keys: &[K],
+    values: &BatchBuffer<V, L>,
+    flags: BpfUpdateElemFlags,
+) -> Result<()> {
+    if keys.len() != values.len() {
+        return Err(Error::Custom(format!(
+            "{}: {} keys for {} values",
+            function_name!(),
+            keys.len(),
+            values.len()
+        )));
+    }
+    let opts = libbpf_sys::bpf_map_batch_opts {
+        sz: mem::size_of::<libbpf_sys::bpf_map_batch_opts>() as _,
+        elem_flags: flags.bits() as u64,
+        flags: 0,
+    };
+    let mut count = keys.len() as u32;
+    match unsafe {
+        libbpf_sys::bpf_map_update_batch(
+            map_fd.fd(),
+            keys.as_ptr() as *mut _,
+            values.as_ptr() as *mut _,
+            &mut count,
+            &opts,
+        )
+    } {
+        0 => Ok(()),
+        _ => map_sys_error(function_name!()),
+    }
+}
+
+/// Thin wrapper around libbpf's bpf_map_delete_batch function (Linux 5.6+).
+/// Returns `Error::KeyNotFound` if the map has no entry for one of `keys`,
+/// the entries of the keys before it being deleted.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_map_delete_batch<K, V, L: MapLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    keys: &[K],
+) -> Result<()> {
+    let mut count = keys.len() as u32;
+    match unsafe {
+        libbpf_sys::bpf_map_delete_batch(
+            map_fd.fd(),
+            keys.as_ptr() as *mut _,
+            &mut count,
+            ptr::null(),
+        )
+    } {
+        0 => Ok(()),
+        _ => map_map_elem_error(function_name!()),
+    }
+}
+
+/// Returns true if the kernel knows the batched map commands (Linux 5.6+).
+/// They are probed with an invalid map fd, rejected with EBADF by kernels
+/// knowing them and with EINVAL, as unknown commands, by older kernels.
+#[cfg(feature = "userspace")]
+pub(crate) fn bpf_map_batch_supported() -> bool {
+    let mut count = 0u32;
+    let err = unsafe {
+        libbpf_sys::bpf_map_delete_batch(-1, ptr::null_mut(), &mut count, ptr::null())
+    };
+    err == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::EINVAL)
+}
+
+/// Maps the errno of a failed element operation, ENOENT being a missing key.
+#[cfg(feature = "userspace")]
+fn map_map_elem_error<T>(function_name: &str) -> Result<T> {
+    let e = std::io::Error::last_os_error();
+    if e.raw_os_error() == Some(libc::ENOENT) {
+        return Err(Error::KeyNotFound);
+    }
+    Err(Error::Sys(function_name.to_owned(), e))
+}
+
+/// Thin wrapper around libbpf's bpf_map_get_next_key function. Returns the key
+/// following `key` in the map, the first key if `key` is None, or None if
+/// `key` is the last one.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_map_get_next_key<K, V, L: MapLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    key: Option<&K>,
+) -> Result<Option<K>> {
+    let key = key.map_or(ptr::null(), to_const_c_void);
+    let mut next_key = maybe_uninit::MaybeUninit::<K>::uninit();
+    match unsafe {
+        libbpf_sys::bpf_map_get_next_key(map_fd.fd(), key, next_key.as_mut_ptr() as *mut _)
+    } {
+        0 => Ok(Some(unsafe { next_key.assume_init() })),
+        _ if std::io::Error::last_os_error().raw_os_error() == Some(libc::ENOENT) => Ok(None),
+        _ => map_sys_error(function_name!()),
+    }
+}
+
 #[cfg(feature = "userspace")]
 #[allow(non_snake_case)]
 pub fn bpf_object__find_program_by_title(
@@ -516,7 +1102,7 @@ pub fn bpf_map__fd<K, V, L: MapLayout<V>>(bpf_map: &BpfMap) -> Result<BpfMapFd<K
     Ok(BpfMapFd::new(fd))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 #[allow(non_camel_case_types)]
 pub enum XdpAction {
@@ -579,6 +1165,452 @@ impl XdpMd {
     }
 }
 
+/// Actions returned by `SCHED_CLS` programs attached in direct-action mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+#[allow(non_camel_case_types)]
+pub enum TcAction {
+    UNSPEC = -1,
+    OK = 0,
+    RECLASSIFY = 1,
+    SHOT = 2,
+    PIPE = 3,
+    STOLEN = 4,
+    QUEUED = 5,
+    REPEAT = 6,
+    REDIRECT = 7,
+}
+
+/// `struct __sk_buff` of the kernel uapi, missing from libbpf-sys. The
+/// pointer fields (`__bpf_md_ptr`) are always 64 bits wide.
+#[repr(C)]
+struct RawSkBuff {
+    len: u32,
+    pkt_type: u32,
+    mark: u32,
+    queue_mapping: u32,
+    protocol: u32,
+    vlan_present: u32,
+    vlan_tci: u32,
+    vlan_proto: u32,
+    priority: u32,
+    ingress_ifindex: u32,
+    ifindex: u32,
+    tc_index: u32,
+    cb: [u32; 5],
+    hash: u32,
+    tc_classid: u32,
+    data: u32,
+    data_end: u32,
+    napi_id: u32,
+    family: u32,
+    remote_ip4: u32,
+    local_ip4: u32,
+    remote_ip6: [u32; 4],
+    local_ip6: [u32; 4],
+    remote_port: u32,
+    local_port: u32,
+    data_meta: u32,
+    flow_keys: u64,
+    tstamp: u64,
+    wire_len: u32,
+    gso_segs: u32,
+    sk: u64,
+}
+
+/// The context of the programs processing socket buffers, such as `SCHED_CLS`
+/// and `SCHED_ACT` programs.
+#[repr(transparent)]
+pub struct SkBuff(RawSkBuff);
+
+#[cfg(feature = "bpf")]
+impl SkBuff {
+    #[inline(always)]
+    pub fn data_buffer(&self) -> Option<&[u8]> {
+        unsafe {
+            let data_buffer: *const u8 = self.0.data as usize as *const u8;
+            if self.0.data_end <= self.0.data {
+                return None;
+            }
+            let data_buffer_size = (self.0.data_end - self.0.data) as usize;
+            Some(std::slice::from_raw_parts(data_buffer, data_buffer_size))
+        }
+    }
+
+    #[inline(always)]
+    pub fn data_pointer(&self) -> (*const u8, *const u8) {
+        (
+            self.0.data as usize as *const u8,
+            self.0.data_end as usize as *const u8,
+        )
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.0.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// The link layer protocol, in network byte order.
+    #[inline(always)]
+    pub fn protocol(&self) -> u32 {
+        self.0.protocol
+    }
+
+    #[inline(always)]
+    pub fn mark(&self) -> u32 {
+        self.0.mark
+    }
+
+    #[inline(always)]
+    pub fn set_mark(&mut self, mark: u32) {
+        self.0.mark = mark
+    }
+
+    #[inline(always)]
+    pub fn priority(&self) -> u32 {
+        self.0.priority
+    }
+
+    #[inline(always)]
+    pub fn ingress_ifindex(&self) -> u32 {
+        self.0.ingress_ifindex
+    }
+
+    #[inline(always)]
+    pub fn ifindex(&self) -> u32 {
+        self.0.ifindex
+    }
+
+    #[inline(always)]
+    pub fn hash(&self) -> u32 {
+        self.0.hash
+    }
+
+    /// The keys to fill by `FLOW_DISSECTOR` programs, None in the other
+    /// program types.
+    #[inline(always)]
+    pub fn flow_keys(&mut self) -> Option<&mut FlowKeys> {
+        unsafe {
+            let flow_keys = self.0.flow_keys as usize as *mut FlowKeys;
+            if flow_keys.is_null() {
+                None
+            } else {
+                Some(&mut *flow_keys)
+            }
+        }
+    }
+}
+
+/// Return codes of `FLOW_DISSECTOR` and lightweight tunnel programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+#[allow(non_camel_case_types)]
+pub enum BpfRetCode {
+    OK = libbpf_sys::BPF_OK,
+    DROP = libbpf_sys::BPF_DROP,
+    REDIRECT = libbpf_sys::BPF_REDIRECT,
+}
+
+/// The result of the dissection of a packet by a `FLOW_DISSECTOR` program,
+/// used by the kernel to hash the packet flow.
+///
+/// The offsets are relative to the beginning of the network header, the
+/// protocols, ports and addresses are in network byte order.
+#[repr(transparent)]
+pub struct FlowKeys(libbpf_sys::bpf_flow_keys);
+
+#[cfg(feature = "bpf")]
+impl FlowKeys {
+    /// Offset of the network header, initially set by the kernel.
+    #[inline(always)]
+    pub fn nhoff(&self) -> u16 {
+        self.0.nhoff
+    }
+
+    #[inline(always)]
+    pub fn set_nhoff(&mut self, nhoff: u16) {
+        self.0.nhoff = nhoff
+    }
+
+    /// Offset of the transport header.
+    #[inline(always)]
+    pub fn thoff(&self) -> u16 {
+        self.0.thoff
+    }
+
+    #[inline(always)]
+    pub fn set_thoff(&mut self, thoff: u16) {
+        self.0.thoff = thoff
+    }
+
+    /// The link layer protocol, initially set by the kernel.
+    #[inline(always)]
+    pub fn n_proto(&self) -> u16 {
+        self.0.n_proto
+    }
+
+    #[inline(always)]
+    pub fn set_n_proto(&mut self, n_proto: u16) {
+        self.0.n_proto = n_proto
+    }
+
+    #[inline(always)]
+    pub fn set_ip_proto(&mut self, ip_proto: u8) {
+        self.0.ip_proto = ip_proto
+    }
+
+    #[inline(always)]
+    pub fn set_fragment(&mut self, is_frag: bool, is_first_frag: bool) {
+        self.0.is_frag = is_frag as u8;
+        self.0.is_first_frag = is_first_frag as u8;
+    }
+
+    #[inline(always)]
+    pub fn set_encap(&mut self, is_encap: bool) {
+        self.0.is_encap = is_encap as u8
+    }
+
+    #[inline(always)]
+    pub fn set_ports(&mut self, sport: u16, dport: u16) {
+        self.0.sport = sport;
+        self.0.dport = dport;
+    }
+
+    /// Set the IPv4 addresses of the flow, and `ETH_P_IP` as address protocol.
+    #[inline(always)]
+    pub fn set_ipv4_addrs(&mut self, src: u32, dst: u32) {
+        self.0.addr_proto = 0x0800;
+        self.0.__bindgen_anon_1.__bindgen_anon_1.ipv4_src = src;
+        self.0.__bindgen_anon_1.__bindgen_anon_1.ipv4_dst = dst;
+    }
+
+    /// Set the IPv6 addresses of the flow, and `ETH_P_IPV6` as address protocol.
+    #[inline(always)]
+    pub fn set_ipv6_addrs(&mut self, src: [u32; 4], dst: [u32; 4]) {
+        self.0.addr_proto = 0x86DD;
+        self.0.__bindgen_anon_1.__bindgen_anon_2.ipv6_src = src;
+        self.0.__bindgen_anon_1.__bindgen_anon_2.ipv6_dst = dst;
+    }
+
+    /// The `BPF_FLOW_DISSECTOR_F_*` flags set by the kernel (Linux 5.4+).
+    #[inline(always)]
+    pub fn flags(&self) -> u32 {
+        self.0.flags
+    }
+
+    #[inline(always)]
+    pub fn set_flow_label(&mut self, flow_label: u32) {
+        self.0.flow_label = flow_label
+    }
+}
+
+/// Verdicts returned by `SK_SKB`, `SK_MSG` and `SK_REUSEPORT` programs, with
+/// the values of `SK_DROP` and `SK_PASS` of the kernel uapi.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+#[allow(non_camel_case_types)]
+pub enum SkAction {
+    DROP = 0,
+    PASS = 1,
+}
+
+bitflags::bitflags! {
+    pub struct SkRedirectFlags: u64 {
+        /// Redirect to the ingress (receive) queue of the target socket
+        /// instead of its egress (send) queue.
+        const INGRESS = 1 << 0;
+    }
+}
+
+/// `struct sk_msg_md` of the kernel uapi, missing from libbpf-sys.
+#[repr(C)]
+struct RawSkMsgMd {
+    data: u64,
+    data_end: u64,
+    family: u32,
+    remote_ip4: u32,
+    local_ip4: u32,
+    remote_ip6: [u32; 4],
+    local_ip6: [u32; 4],
+    remote_port: u32,
+    local_port: u32,
+    size: u32,
+}
+
+/// The context of `SK_MSG` programs: a message sent on a socket of a
+/// `SockMap` or `SockHash`.
+#[repr(transparent)]
+pub struct SkMsgMd(RawSkMsgMd);
+
+#[cfg(feature = "bpf")]
+impl SkMsgMd {
+    #[inline(always)]
+    pub fn family(&self) -> u32 {
+        self.0.family
+    }
+
+    /// The remote IPv4 address, in network byte order.
+    #[inline(always)]
+    pub fn remote_ip4(&self) -> u32 {
+        self.0.remote_ip4
+    }
+
+    /// The local IPv4 address, in network byte order.
+    #[inline(always)]
+    pub fn local_ip4(&self) -> u32 {
+        self.0.local_ip4
+    }
+
+    /// The remote port, in network byte order.
+    #[inline(always)]
+    pub fn remote_port(&self) -> u32 {
+        self.0.remote_port
+    }
+
+    /// The local port, in host byte order.
+    #[inline(always)]
+    pub fn local_port(&self) -> u32 {
+        self.0.local_port
+    }
+
+    /// The total size of the message.
+    #[inline(always)]
+    pub fn size(&self) -> u32 {
+        self.0.size
+    }
+}
+
+/// `struct sk_reuseport_md` of the kernel uapi, missing from libbpf-sys.
+#[repr(C)]
+struct RawSkReuseportMd {
+    data: u64,
+    data_end: u64,
+    len: u32,
+    eth_protocol: u32,
+    ip_protocol: u32,
+    bind_inany: u32,
+    hash: u32,
+}
+
+/// The context of `SK_REUSEPORT` programs: the packet for which a socket
+/// of a reuseport group must be selected.
+#[repr(transparent)]
+pub struct SkReuseportMd(RawSkReuseportMd);
+
+#[cfg(feature = "bpf")]
+impl SkReuseportMd {
+    /// The packet, starting at the transport header for UDP and at the TCP
+    /// header for TCP.
+    #[inline(always)]
+    pub fn data_pointer(&self) -> (*const u8, *const u8) {
+        (
+            self.0.data as usize as *const u8,
+            self.0.data_end as usize as *const u8,
+        )
+    }
+
+    /// The total length of the packet.
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.0.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// The link layer protocol, in network byte order.
+    #[inline(always)]
+    pub fn eth_protocol(&self) -> u32 {
+        self.0.eth_protocol
+    }
+
+    #[inline(always)]
+    pub fn ip_protocol(&self) -> u32 {
+        self.0.ip_protocol
+    }
+
+    /// Whether the sockets of the group are bound to a wildcard address.
+    #[inline(always)]
+    pub fn bind_inany(&self) -> bool {
+        self.0.bind_inany != 0
+    }
+
+    /// The flow hash of the packet.
+    #[inline(always)]
+    pub fn hash(&self) -> u32 {
+        self.0.hash
+    }
+}
+
+/// The context of `KPROBE` programs: the registers of the probed task,
+/// laid out as `struct pt_regs` on x86_64, the only supported architecture.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+pub struct PtRegs {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub eflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+#[cfg(all(feature = "bpf", target_arch = "x86_64"))]
+impl PtRegs {
+    /// Returns the `n`-th argument of the probed function, following the
+    /// System V calling convention (only the first six arguments are in registers).
+    #[inline(always)]
+    pub fn arg(&self, n: u32) -> Option<u64> {
+        match n {
+            0 => Some(self.rdi),
+            1 => Some(self.rsi),
+            2 => Some(self.rdx),
+            3 => Some(self.rcx),
+            4 => Some(self.r8),
+            5 => Some(self.r9),
+            _ => None,
+        }
+    }
+
+    /// Returns the return value of the probed function, in return probes.
+    #[inline(always)]
+    pub fn ret(&self) -> u64 {
+        self.rax
+    }
+
+    #[inline(always)]
+    pub fn ip(&self) -> u64 {
+        self.rip
+    }
+
+    #[inline(always)]
+    pub fn sp(&self) -> u64 {
+        self.rsp
+    }
+}
+
 #[cfg(feature = "userspace")]
 #[named]
 pub fn bpf_set_link_xdp_fd(
@@ -587,8 +1619,10 @@ pub fn bpf_set_link_xdp_fd(
     xdp_flags: XdpFlags,
 ) -> Result<()> {
     let fd = bpf_fd.map_or(-1, |f| f.fd);
-    let err =
-        unsafe { libbpf_sys::bpf_set_link_xdp_fd(interface.ifindex as i32, fd, xdp_flags.bits()) };
+    let ifindex = interface.ifindex as i32;
+    let err = interface.run_in_netns(move || unsafe {
+        libbpf_sys::bpf_set_link_xdp_fd(ifindex, fd, xdp_flags.bits())
+    })?;
     if err < 0 {
         return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(err));
     }
@@ -596,6 +1630,120 @@ pub fn bpf_set_link_xdp_fd(
     Ok(())
 }
 
+#[cfg(feature = "userspace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(non_camel_case_types)]
+pub enum XdpAttachMode {
+    NONE = libbpf_sys::XDP_ATTACHED_NONE as u8,
+    DRV = libbpf_sys::XDP_ATTACHED_DRV as u8,
+    SKB = libbpf_sys::XDP_ATTACHED_SKB as u8,
+    HW = libbpf_sys::XDP_ATTACHED_HW as u8,
+    MULTI = libbpf_sys::XDP_ATTACHED_MULTI as u8,
+}
+
+/// The XDP programs attached to an interface, as reported by the kernel.
+/// A program id equal to 0 means that no program is attached.
+#[cfg(feature = "userspace")]
+pub struct XdpLinkInfo {
+    info: libbpf_sys::xdp_link_info,
+}
+
+#[cfg(feature = "userspace")]
+impl XdpLinkInfo {
+    /// Id of the attached program when a single program is attached,
+    /// whatever its mode.
+    pub fn prog_id(&self) -> u32 {
+        self.info.prog_id
+    }
+
+    pub fn drv_prog_id(&self) -> u32 {
+        self.info.drv_prog_id
+    }
+
+    pub fn skb_prog_id(&self) -> u32 {
+        self.info.skb_prog_id
+    }
+
+    pub fn hw_prog_id(&self) -> u32 {
+        self.info.hw_prog_id
+    }
+
+    pub fn attach_mode(&self) -> XdpAttachMode {
+        let attach_mode: XdpAttachMode = unsafe { std::mem::transmute(self.info.attach_mode) };
+        attach_mode
+    }
+
+    /// Returns the id of the program attached in the mode selected by
+    /// `xdp_flags`, or None if no program is attached in this mode.
+    pub fn prog_id_by_mode(&self, xdp_flags: XdpFlags) -> Option<u32> {
+        let id = if xdp_flags.contains(XdpFlags::SKB_MODE) {
+            self.skb_prog_id()
+        } else if xdp_flags.contains(XdpFlags::DRV_MODE) {
+            self.drv_prog_id()
+        } else if xdp_flags.contains(XdpFlags::HW_MODE) {
+            self.hw_prog_id()
+        } else {
+            self.prog_id()
+        };
+        if id == 0 {
+            None
+        } else {
+            Some(id)
+        }
+    }
+}
+
+/// Returns the id of the XDP program attached to `interface` in the mode
+/// selected by `xdp_flags`, or None if no program is attached.
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_get_link_xdp_id(
+    interface: &interface::Interface,
+    xdp_flags: XdpFlags,
+) -> Result<Option<u32>> {
+    let ifindex = interface.ifindex as i32;
+    let (err, prog_id) = interface.run_in_netns(move || {
+        let mut prog_id: u32 = 0;
+        let err = unsafe { libbpf_sys::bpf_get_link_xdp_id(ifindex, &mut prog_id, xdp_flags.bits()) };
+        (err, prog_id)
+    })?;
+    if err < 0 {
+        return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(err));
+    }
+    if prog_id == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(prog_id))
+}
+
+#[cfg(feature = "userspace")]
+#[named]
+pub fn bpf_get_link_xdp_info(
+    interface: &interface::Interface,
+    xdp_flags: XdpFlags,
+) -> Result<XdpLinkInfo> {
+    let ifindex = interface.ifindex as i32;
+    let (err, info) = interface.run_in_netns(move || {
+        let mut info: libbpf_sys::xdp_link_info = unsafe { mem::zeroed() };
+        let err = unsafe {
+            libbpf_sys::bpf_get_link_xdp_info(
+                ifindex,
+                &mut info,
+                mem::size_of::<libbpf_sys::xdp_link_info>() as _,
+                xdp_flags.bits(),
+            )
+        };
+        (err, info)
+    })?;
+    if err < 0 {
+        return map_libbpf_error(function_name!(), LibbpfError::LibbpfSys(err));
+    }
+
+    Ok(XdpLinkInfo { info })
+}
+
 #[cfg(feature = "userspace")]
 #[named]
 pub fn libbpf_num_possible_cpus() -> Result<i32> {
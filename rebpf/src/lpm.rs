@@ -0,0 +1,159 @@
+//! This module contains the keys of the LPM (longest prefix match) trie maps,
+//! shared by the kernel side ([`bpf::maps::LpmTrie`]) and the userspace side
+//! ([`userspace::maps::LpmTrie`]).
+//!
+//! A key is a prefix length followed by the data, compared bit by bit from
+//! the most significant bit of its first byte, i.e. an IP address in network
+//! byte order.
+//!
+//! [`bpf::maps::LpmTrie`]: ../bpf/maps/struct.LpmTrie.html
+//! [`userspace::maps::LpmTrie`]: ../userspace/maps/struct.LpmTrie.html
+
+use crate::error::{Error, Result};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+/// The `BPF_F_NO_PREALLOC` flag, required by the kernel for LPM tries.
+pub const LPM_TRIE_MAP_FLAGS: u32 = libbpf_sys::BPF_F_NO_PREALLOC;
+
+/// The data of an LPM trie key.
+pub trait LpmData: Copy {
+    /// The size of the data in bits, i.e. the maximal prefix length.
+    const BITS: u32;
+
+    fn bytes_mut(&mut self) -> &mut [u8];
+
+    /// Parse the address part of a CIDR.
+    fn parse_addr(s: &str) -> Option<Self>;
+}
+
+impl LpmData for [u8; 4] {
+    const BITS: u32 = 32;
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self
+    }
+
+    fn parse_addr(s: &str) -> Option<Self> {
+        Ipv4Addr::from_str(s).ok().map(|addr| addr.octets())
+    }
+}
+
+impl LpmData for [u8; 16] {
+    const BITS: u32 = 128;
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self
+    }
+
+    fn parse_addr(s: &str) -> Option<Self> {
+        Ipv6Addr::from_str(s).ok().map(|addr| addr.octets())
+    }
+}
+
+/// A key of an LPM trie: the first `prefix_len` bits of `data`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LpmKey<T> {
+    prefix_len: u32,
+    data: T,
+}
+
+impl<T> LpmKey<T> {
+    /// A key made of the first `prefix_len` bits of `data`. The bits of
+    /// `data` past the prefix are ignored by the kernel.
+    pub const fn new(prefix_len: u32, data: T) -> LpmKey<T> {
+        LpmKey { prefix_len, data }
+    }
+
+    pub fn prefix_len(&self) -> u32 {
+        self.prefix_len
+    }
+
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+}
+
+impl<T: LpmData> LpmKey<T> {
+    /// A key made of all the bits of `data`, as used to lookup an address.
+    pub fn exact(data: T) -> LpmKey<T> {
+        LpmKey::new(T::BITS, data)
+    }
+
+    /// Parse a CIDR, i.e. `10.0.0.0/8` or `2001:db8::/32`. The bits past the
+    /// prefix are cleared, and the prefix length defaults to all the bits.
+    pub fn parse_cidr(cidr: &str) -> Option<LpmKey<T>> {
+        let mut parts = cidr.trim().splitn(2, '/');
+        let mut data = T::parse_addr(parts.next()?)?;
+        let prefix_len = match parts.next() {
+            Some(prefix_len) => prefix_len.parse().ok()?,
+            None => T::BITS,
+        };
+        if prefix_len > T::BITS {
+            return None;
+        }
+        for (i, byte) in data.bytes_mut().iter_mut().enumerate() {
+            let kept = prefix_len.saturating_sub(i as u32 * 8).min(8);
+            *byte &= !(0xFFu16 >> kept) as u8;
+        }
+        Some(LpmKey::new(prefix_len, data))
+    }
+}
+
+impl<T: LpmData> FromStr for LpmKey<T> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<LpmKey<T>> {
+        LpmKey::parse_cidr(s).ok_or_else(|| Error::Custom(format!("Invalid CIDR: {}", s)))
+    }
+}
+
+impl From<Ipv4Addr> for LpmKey<[u8; 4]> {
+    fn from(addr: Ipv4Addr) -> Self {
+        LpmKey::exact(addr.octets())
+    }
+}
+
+impl From<Ipv6Addr> for LpmKey<[u8; 16]> {
+    fn from(addr: Ipv6Addr) -> Self {
+        LpmKey::exact(addr.octets())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_ipv4_cidrs() {
+        assert_eq!(
+            "10.1.2.3/8".parse::<LpmKey<[u8; 4]>>().ok(),
+            Some(LpmKey::new(8, [10, 0, 0, 0]))
+        );
+        assert_eq!(
+            LpmKey::<[u8; 4]>::parse_cidr("192.168.1.129/25"),
+            Some(LpmKey::new(25, [192, 168, 1, 128]))
+        );
+        assert_eq!(
+            LpmKey::<[u8; 4]>::parse_cidr("192.168.1.1"),
+            Some(LpmKey::from(Ipv4Addr::new(192, 168, 1, 1)))
+        );
+        assert_eq!(LpmKey::<[u8; 4]>::parse_cidr("0.0.0.0/0"), Some(LpmKey::new(0, [0; 4])));
+        assert_eq!(LpmKey::<[u8; 4]>::parse_cidr("10.0.0.0/33"), None);
+        assert_eq!(LpmKey::<[u8; 4]>::parse_cidr("2001:db8::/32"), None);
+    }
+
+    #[test]
+    fn parse_ipv6_cidrs() {
+        let key = LpmKey::<[u8; 16]>::parse_cidr("2001:db8:ffff::1/36").unwrap();
+        assert_eq!(key.prefix_len(), 36);
+        assert_eq!(
+            Ipv6Addr::from(*key.data()),
+            "2001:db8:f000::".parse::<Ipv6Addr>().unwrap()
+        );
+        assert!(LpmKey::<[u8; 16]>::parse_cidr("::/129").is_none());
+    }
+}
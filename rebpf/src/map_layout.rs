@@ -4,7 +4,9 @@
 use lazy_static::lazy_static;
 use maybe_uninit::MaybeUninit;
 use std::ffi::c_void;
+use std::marker::PhantomData;
 use std::os::raw;
+use std::{mem, ptr};
 
 pub trait PtrCheckedMut<T, L: MapLayout<T> + ?Sized> {
     /// Get a pointer to the beginning of the buffer to pass
@@ -68,6 +70,40 @@ pub trait MapLayout<T> {
     unsafe fn transmute(buffer: Self::WritableBuffer) -> Self::Buffer;
 }
 
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for super::ScalarLayout {}
+    impl Sealed for super::PerCpuLayout {}
+}
+
+/// The layouts whose values can be stored contiguously in a `BatchBuffer`.
+/// This trait is sealed: it is implemented by the layouts of this module only.
+pub trait BatchLayout<T>: MapLayout<T> + private::Sealed {
+    /// The size of a value as seen by the BPF calls, i.e. the stride of the
+    /// values of a `BatchBuffer`.
+    fn value_size() -> usize;
+
+    /// Copy the value stored at `src` by a BPF call.
+    ///
+    /// # Safety
+    ///
+    /// `src` must point to `value_size()` bytes holding a valid value.
+    unsafe fn read_value(src: *const u8) -> Self::Buffer;
+
+    /// Copy `value` to `dst` for a BPF call to read it.
+    ///
+    /// # Safety
+    ///
+    /// `dst` must point to `value_size()` writable bytes.
+    ///
+    /// # Panics
+    ///
+    /// This function may panic if the buffer doesn't hold the invariants
+    /// required by the layout.
+    unsafe fn write_value(value: &Self::Buffer, dst: *mut u8);
+}
+
 /// The simplest data layout, a single, scalar value.
 /// Used by the most basic BPF maps, such as the Array and HashMap
 pub struct ScalarLayout;
@@ -91,6 +127,20 @@ impl<T> MapLayout<T> for ScalarLayout {
     }
 }
 
+impl<T> BatchLayout<T> for ScalarLayout {
+    fn value_size() -> usize {
+        mem::size_of::<T>()
+    }
+
+    unsafe fn read_value(src: *const u8) -> Self::Buffer {
+        ptr::read_unaligned(src as *const T)
+    }
+
+    unsafe fn write_value(value: &Self::Buffer, dst: *mut u8) {
+        ptr::copy_nonoverlapping(value as *const T as *const u8, dst, mem::size_of::<T>());
+    }
+}
+
 impl<T> PtrCheckedMut<T, ScalarLayout> for MaybeUninit<T> {
     fn ptr_checked_mut(&mut self) -> *mut c_void {
         self.as_mut_ptr() as *mut c_void
@@ -176,6 +226,33 @@ impl<T> MapLayout<T> for PerCpuLayout {
     }
 }
 
+impl<T> BatchLayout<T> for PerCpuLayout {
+    fn value_size() -> usize {
+        mem::size_of::<PerCpuValue<T>>() * Self::nb_cpus()
+    }
+
+    unsafe fn read_value(src: *const u8) -> Self::Buffer {
+        let src = src as *const PerCpuValue<T>;
+        (0..Self::nb_cpus())
+            .map(|cpu| ptr::read_unaligned(src.add(cpu)))
+            .collect::<Vec<_>>()
+            .into_boxed_slice()
+    }
+
+    /// # Panics
+    ///
+    /// This will panic if `value` hasn't a size exactly equal to the number
+    /// of CPUs on the system, as exposed via `PerCpuLayout::nb_cpus()`
+    unsafe fn write_value(value: &Self::Buffer, dst: *mut u8) {
+        let src = PtrChecked::<T, PerCpuLayout>::ptr_checked(value);
+        ptr::copy_nonoverlapping(
+            src as *const u8,
+            dst,
+            <Self as BatchLayout<T>>::value_size(),
+        );
+    }
+}
+
 impl<T> PtrChecked<T, PerCpuLayout> for Box<[PerCpuValue<T>]> {
     fn ptr_checked(&self) -> *const c_void {
         assert!(self.len() == PerCpuLayout::nb_cpus(), "size mismatch");
@@ -190,6 +267,75 @@ impl<T> PtrCheckedMut<T, PerCpuLayout> for Box<[MaybeUninit<PerCpuValue<T>>]> {
     }
 }
 
+/// Values stored contiguously, as expected by the batched BPF calls.
+pub struct BatchBuffer<T, L: BatchLayout<T>> {
+    // u64 words keep the values 8-byte aligned, as per-CPU values must be.
+    data: Vec<u64>,
+    len: usize,
+    _layout: PhantomData<fn() -> (T, L)>,
+}
+
+impl<T, L: BatchLayout<T>> BatchBuffer<T, L> {
+    /// Allocate a zeroed buffer holding `len` values.
+    pub fn with_len(len: usize) -> Self {
+        let words = (len * L::value_size()).div_ceil(8);
+        BatchBuffer {
+            data: vec![0; words],
+            len,
+            _layout: PhantomData,
+        }
+    }
+
+    /// Copy `values` into a new buffer.
+    ///
+    /// # Panics
+    ///
+    /// This function may panic if a value doesn't hold the invariants
+    /// required by the layout.
+    pub fn from_values(values: &[L::Buffer]) -> Self {
+        let mut buffer = Self::with_len(values.len());
+        for (i, value) in values.iter().enumerate() {
+            unsafe { L::write_value(value, buffer.value_ptr_mut(i)) };
+        }
+        buffer
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a copy of the value at `index`.
+    ///
+    /// # Safety
+    ///
+    /// The value must have been written by a successful BPF call, or come
+    /// from `from_values`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `index` is out of bounds.
+    pub unsafe fn read(&self, index: usize) -> L::Buffer {
+        assert!(index < self.len, "index out of bounds");
+        L::read_value((self.data.as_ptr() as *const u8).add(index * L::value_size()))
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const c_void {
+        self.data.as_ptr() as *const c_void
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut c_void {
+        self.data.as_mut_ptr() as *mut c_void
+    }
+
+    fn value_ptr_mut(&mut self, index: usize) -> *mut u8 {
+        unsafe { (self.data.as_mut_ptr() as *mut u8).add(index * L::value_size()) }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -229,7 +375,7 @@ mod test {
     fn percpu_transmute() {
         let mut buffer = PerCpuLayout::allocate_write();
         for i in 0..buffer.len() {
-            std::mem::swap(&mut buffer[i], &mut MaybeUninit::new(PerCpuValue(i)));
+            buffer[i] = MaybeUninit::new(PerCpuValue(i));
         }
 
         let buffer = unsafe { PerCpuLayout::transmute(buffer) };
@@ -313,4 +459,23 @@ mod test {
         assert_eq!(size_of::<PerCpuValue<U32X3>>(), 16);
         assert_eq!(align_of::<PerCpuValue<U32X3>>(), 8);
     }
+
+    #[test]
+    fn scalar_batch_buffer() {
+        let buffer = BatchBuffer::<(u8, u16), ScalarLayout>::from_values(&[(1, 2), (3, 4)]);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(unsafe { buffer.read(1) }, (3, 4));
+    }
+
+    #[test]
+    fn percpu_batch_buffer() {
+        let values = vec![
+            PerCpuLayout::allocate(|| 1u32),
+            PerCpuLayout::allocate(|| 2u32),
+        ];
+        let buffer = BatchBuffer::<u32, PerCpuLayout>::from_values(&values);
+        let value = unsafe { buffer.read(1) };
+        assert_eq!(value.len(), PerCpuLayout::nb_cpus());
+        assert!(value.iter().all(|v| *v.as_ref() == 2));
+    }
 }
@@ -0,0 +1,469 @@
+//! This module contains a minimal rtnetlink client for internal use.
+
+use crate::{error::Result, netns::NetNs, utils::*};
+use std::{mem, os::unix::io::RawFd, ptr};
+
+pub(crate) const NLMSG_ERROR: u16 = 2;
+pub(crate) const NLMSG_DONE: u16 = 3;
+
+pub(crate) const NLM_F_REQUEST: u16 = 0x01;
+pub(crate) const NLM_F_ACK: u16 = 0x04;
+pub(crate) const NLM_F_DUMP: u16 = 0x300;
+pub(crate) const NLM_F_EXCL: u16 = 0x200;
+pub(crate) const NLM_F_CREATE: u16 = 0x400;
+
+pub(crate) const NLA_F_NESTED: u16 = 1 << 15;
+const NLA_TYPE_MASK: u16 = !(NLA_F_NESTED | (1 << 14));
+
+/// Multicast group of the link notifications.
+pub(crate) const RTMGRP_LINK: u32 = 1;
+
+pub(crate) const RTM_NEWLINK: u16 = 16;
+pub(crate) const RTM_DELLINK: u16 = 17;
+pub(crate) const RTM_GETLINK: u16 = 18;
+pub(crate) const RTM_SETLINK: u16 = 19;
+
+pub(crate) const RTM_NEWADDR: u16 = 20;
+
+pub(crate) const RTM_NEWQDISC: u16 = 36;
+pub(crate) const RTM_DELQDISC: u16 = 37;
+pub(crate) const RTM_NEWTFILTER: u16 = 44;
+pub(crate) const RTM_DELTFILTER: u16 = 45;
+pub(crate) const RTM_GETTFILTER: u16 = 46;
+
+pub(crate) const IFLA_ADDRESS: u16 = 1;
+pub(crate) const IFLA_IFNAME: u16 = 3;
+pub(crate) const IFLA_MTU: u16 = 4;
+pub(crate) const IFLA_LINKINFO: u16 = 18;
+pub(crate) const IFLA_NET_NS_FD: u16 = 28;
+pub(crate) const IFLA_NUM_TX_QUEUES: u16 = 31;
+pub(crate) const IFLA_NUM_RX_QUEUES: u16 = 32;
+pub(crate) const IFLA_XDP: u16 = 43;
+
+pub(crate) const IFLA_INFO_KIND: u16 = 1;
+pub(crate) const IFLA_INFO_DATA: u16 = 2;
+
+pub(crate) const IFA_ADDRESS: u16 = 1;
+pub(crate) const IFA_LOCAL: u16 = 2;
+
+pub(crate) const IFLA_XDP_FD: u16 = 1;
+pub(crate) const IFLA_XDP_FLAGS: u16 = 3;
+pub(crate) const IFLA_XDP_EXPECTED_FD: u16 = 8;
+
+const NLMSG_ALIGNTO: usize = 4;
+const NLA_ALIGNTO: usize = 4;
+const NLMSG_HDRLEN: usize = mem::size_of::<NlMsgHdr>();
+const NLA_HDRLEN: usize = mem::size_of::<NlAttr>();
+const RECV_BUFFER_SIZE: usize = 32 * 1024;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct NlMsgHdr {
+    nlmsg_len: u32,
+    nlmsg_type: u16,
+    nlmsg_flags: u16,
+    nlmsg_seq: u32,
+    nlmsg_pid: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct NlAttr {
+    nla_len: u16,
+    nla_type: u16,
+}
+
+/// `struct ifinfomsg` from linux/rtnetlink.h.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct IfInfoMsg {
+    pub(crate) ifi_family: u8,
+    pub(crate) ifi_pad: u8,
+    pub(crate) ifi_type: u16,
+    pub(crate) ifi_index: i32,
+    pub(crate) ifi_flags: u32,
+    pub(crate) ifi_change: u32,
+}
+
+/// `struct ifaddrmsg` from linux/if_addr.h.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct IfAddrMsg {
+    pub(crate) ifa_family: u8,
+    pub(crate) ifa_prefixlen: u8,
+    pub(crate) ifa_flags: u8,
+    pub(crate) ifa_scope: u8,
+    pub(crate) ifa_index: u32,
+}
+
+/// `struct tcmsg` from linux/rtnetlink.h.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct TcMsg {
+    pub(crate) tcm_family: u8,
+    pub(crate) tcm_pad1: u8,
+    pub(crate) tcm_pad2: u16,
+    pub(crate) tcm_ifindex: i32,
+    pub(crate) tcm_handle: u32,
+    pub(crate) tcm_parent: u32,
+    pub(crate) tcm_info: u32,
+}
+
+fn nlmsg_align(len: usize) -> usize {
+    (len + NLMSG_ALIGNTO - 1) & !(NLMSG_ALIGNTO - 1)
+}
+
+fn nla_align(len: usize) -> usize {
+    (len + NLA_ALIGNTO - 1) & !(NLA_ALIGNTO - 1)
+}
+
+/// Returns the bytes of a plain old data struct.
+pub(crate) fn as_bytes<T: Copy>(v: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(v as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+/// Reads a plain old data struct from the beginning of `buf`.
+pub(crate) fn from_bytes<T: Copy>(buf: &[u8]) -> Option<T> {
+    if buf.len() < mem::size_of::<T>() {
+        return None;
+    }
+    Some(unsafe { ptr::read_unaligned(buf.as_ptr() as *const T) })
+}
+
+/// A netlink request under construction: a netlink header, a family
+/// specific header and a sequence of (possibly nested) attributes.
+pub(crate) struct NetlinkMessage {
+    buf: Vec<u8>,
+    nested: Vec<usize>,
+}
+
+impl NetlinkMessage {
+    pub(crate) fn new<T: Copy>(msg_type: u16, flags: u16, header: &T) -> NetlinkMessage {
+        let hdr = NlMsgHdr {
+            nlmsg_len: 0,
+            nlmsg_type: msg_type,
+            nlmsg_flags: NLM_F_REQUEST | flags,
+            nlmsg_seq: 0,
+            nlmsg_pid: 0,
+        };
+        let mut msg = NetlinkMessage {
+            buf: Vec::with_capacity(256),
+            nested: Vec::new(),
+        };
+        msg.buf.extend_from_slice(as_bytes(&hdr));
+        msg.push_aligned(as_bytes(header));
+        msg
+    }
+
+    fn push_aligned(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+        let len = nla_align(self.buf.len());
+        self.buf.resize(len, 0);
+    }
+
+    /// Push a family specific header, i.e. in a nested attribute.
+    pub(crate) fn push_header<T: Copy>(&mut self, header: &T) {
+        self.push_aligned(as_bytes(header));
+    }
+
+    pub(crate) fn push_attr(&mut self, attr_type: u16, data: &[u8]) {
+        let attr = NlAttr {
+            nla_len: (NLA_HDRLEN + data.len()) as u16,
+            nla_type: attr_type,
+        };
+        self.buf.extend_from_slice(as_bytes(&attr));
+        self.push_aligned(data);
+    }
+
+    pub(crate) fn push_attr_u32(&mut self, attr_type: u16, v: u32) {
+        self.push_attr(attr_type, as_bytes(&v));
+    }
+
+    pub(crate) fn push_attr_i32(&mut self, attr_type: u16, v: i32) {
+        self.push_attr(attr_type, as_bytes(&v));
+    }
+
+    /// Push a NUL-terminated string attribute.
+    pub(crate) fn push_attr_str(&mut self, attr_type: u16, s: &str) {
+        let mut data = Vec::with_capacity(s.len() + 1);
+        data.extend_from_slice(s.as_bytes());
+        data.push(0);
+        self.push_attr(attr_type, &data);
+    }
+
+    /// Open a nested attribute: every attribute pushed until the matching
+    /// `end_nested` call is a child of this one.
+    pub(crate) fn begin_nested(&mut self, attr_type: u16) {
+        self.nested.push(self.buf.len());
+        self.push_attr(attr_type | NLA_F_NESTED, &[]);
+    }
+
+    pub(crate) fn end_nested(&mut self) {
+        let begin = self
+            .nested
+            .pop()
+            .expect("end_nested called without begin_nested");
+        let len = (self.buf.len() - begin) as u16;
+        self.buf[begin..begin + 2].copy_from_slice(as_bytes(&len));
+    }
+
+    fn add_flags(&mut self, flags: u16) {
+        let mut hdr: NlMsgHdr = from_bytes(&self.buf).unwrap();
+        hdr.nlmsg_flags |= flags;
+        self.buf[..NLMSG_HDRLEN].copy_from_slice(as_bytes(&hdr));
+    }
+
+    fn finalize(&mut self, seq: u32) -> &[u8] {
+        assert!(self.nested.is_empty(), "unterminated nested attribute");
+        let mut hdr: NlMsgHdr = from_bytes(&self.buf).unwrap();
+        hdr.nlmsg_len = self.buf.len() as u32;
+        hdr.nlmsg_seq = seq;
+        self.buf[..NLMSG_HDRLEN].copy_from_slice(as_bytes(&hdr));
+        &self.buf
+    }
+}
+
+/// Iterator over the netlink attributes contained in a buffer.
+pub(crate) struct NetlinkAttrs<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for NetlinkAttrs<'a> {
+    /// The attribute type (without the nested flag) and its payload.
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let attr: NlAttr = from_bytes(self.buf)?;
+        let len = attr.nla_len as usize;
+        if len < NLA_HDRLEN || len > self.buf.len() {
+            return None;
+        }
+        let payload = &self.buf[NLA_HDRLEN..len];
+        let next = nla_align(len).min(self.buf.len());
+        self.buf = &self.buf[next..];
+        Some((attr.nla_type & NLA_TYPE_MASK, payload))
+    }
+}
+
+pub(crate) fn parse_attrs(buf: &[u8]) -> NetlinkAttrs<'_> {
+    NetlinkAttrs { buf }
+}
+
+pub(crate) fn attr_u32(payload: &[u8]) -> Option<u32> {
+    from_bytes(payload)
+}
+
+pub(crate) fn attr_str(payload: &[u8]) -> Option<String> {
+    let end = payload.iter().position(|&b| b == 0).unwrap_or(payload.len());
+    std::str::from_utf8(&payload[..end]).ok().map(String::from)
+}
+
+/// A netlink message received from the kernel.
+pub(crate) struct NetlinkReply {
+    pub(crate) msg_type: u16,
+    /// The message payload, starting with the family specific header.
+    pub(crate) payload: Vec<u8>,
+}
+
+impl NetlinkReply {
+    /// Returns the family specific header and the attributes following it.
+    pub(crate) fn split<T: Copy>(&self) -> Option<(T, NetlinkAttrs<'_>)> {
+        let header: T = from_bytes(&self.payload)?;
+        let attrs_offset = nla_align(mem::size_of::<T>()).min(self.payload.len());
+        Some((header, parse_attrs(&self.payload[attrs_offset..])))
+    }
+}
+
+pub(crate) struct NetlinkSocket {
+    fd: RawFd,
+    seq: u32,
+}
+
+impl NetlinkSocket {
+    /// Open a NETLINK_ROUTE socket.
+    pub(crate) fn new() -> Result<NetlinkSocket> {
+        NetlinkSocket::subscribe(0)
+    }
+
+    /// Open a NETLINK_ROUTE socket subscribed to the multicast `groups`.
+    #[named]
+    pub(crate) fn subscribe(groups: u32) -> Result<NetlinkSocket> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return map_sys_error(function_name!());
+        }
+        let socket = NetlinkSocket { fd, seq: 0 };
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        addr.nl_groups = groups;
+        let err = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as u32,
+            )
+        };
+        if err < 0 {
+            return map_sys_error(function_name!());
+        }
+        Ok(socket)
+    }
+
+    /// Open a NETLINK_ROUTE socket in the network namespace `netns`, or in
+    /// the namespace of the calling thread if None.
+    pub(crate) fn new_in(netns: Option<&NetNs>) -> Result<NetlinkSocket> {
+        NetlinkSocket::subscribe_in(netns, 0)
+    }
+
+    /// Open a NETLINK_ROUTE socket subscribed to the multicast `groups` in
+    /// the network namespace `netns`, or in the namespace of the calling
+    /// thread if None.
+    pub(crate) fn subscribe_in(netns: Option<&NetNs>, groups: u32) -> Result<NetlinkSocket> {
+        match netns {
+            // A netlink socket stays bound to the namespace it was created in.
+            Some(netns) => netns.run(move || NetlinkSocket::subscribe(groups))?,
+            None => NetlinkSocket::subscribe(groups),
+        }
+    }
+
+    pub(crate) fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    #[named]
+    fn send(&mut self, msg: &mut NetlinkMessage) -> Result<u32> {
+        self.seq = self.seq.wrapping_add(1);
+        let seq = self.seq;
+        let buf = msg.finalize(seq);
+        let sent = unsafe { libc::send(self.fd, buf.as_ptr() as *const _, buf.len(), 0) };
+        if sent < 0 {
+            return map_sys_error(function_name!());
+        }
+        Ok(seq)
+    }
+
+    /// Receive the messages contained in the next datagram.
+    #[named]
+    pub(crate) fn recv(&mut self) -> Result<Vec<(NlMsgHdrInfo, NetlinkReply)>> {
+        let mut buf = vec![0u8; RECV_BUFFER_SIZE];
+        let len = loop {
+            let len =
+                unsafe { libc::recv(self.fd, buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+            if len >= 0 {
+                break len as usize;
+            }
+            if std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                return map_sys_error(function_name!());
+            }
+        };
+        let mut replies = Vec::new();
+        let mut buf = &buf[..len];
+        while let Some(hdr) = from_bytes::<NlMsgHdr>(buf) {
+            let msg_len = hdr.nlmsg_len as usize;
+            if msg_len < NLMSG_HDRLEN || msg_len > buf.len() {
+                break;
+            }
+            replies.push((
+                NlMsgHdrInfo {
+                    seq: hdr.nlmsg_seq,
+                },
+                NetlinkReply {
+                    msg_type: hdr.nlmsg_type,
+                    payload: buf[NLMSG_HDRLEN..msg_len].to_vec(),
+                },
+            ));
+            buf = &buf[nlmsg_align(msg_len).min(buf.len())..];
+        }
+        Ok(replies)
+    }
+
+    /// Send a request and wait for the kernel acknowledgement.
+    pub(crate) fn request(&mut self, function_name: &str, mut msg: NetlinkMessage) -> Result<()> {
+        msg.add_flags(NLM_F_ACK);
+        let seq = self.send(&mut msg)?;
+        loop {
+            for (hdr, reply) in self.recv()? {
+                if hdr.seq != seq {
+                    continue;
+                }
+                if reply.msg_type == NLMSG_ERROR {
+                    return check_ack(function_name, &reply);
+                }
+            }
+        }
+    }
+
+    /// Send a get request and wait for the single object sent in reply.
+    pub(crate) fn get(
+        &mut self,
+        function_name: &str,
+        mut msg: NetlinkMessage,
+    ) -> Result<NetlinkReply> {
+        let seq = self.send(&mut msg)?;
+        loop {
+            for (hdr, reply) in self.recv()? {
+                if hdr.seq != seq {
+                    continue;
+                }
+                if reply.msg_type == NLMSG_ERROR {
+                    check_ack(function_name, &reply)?;
+                    return map_errno_error(function_name, libc::EPROTO);
+                }
+                return Ok(reply);
+            }
+        }
+    }
+
+    /// Send a dump request and collect every reply until the end of the dump.
+    pub(crate) fn dump(
+        &mut self,
+        function_name: &str,
+        mut msg: NetlinkMessage,
+    ) -> Result<Vec<NetlinkReply>> {
+        msg.add_flags(NLM_F_DUMP);
+        let seq = self.send(&mut msg)?;
+        let mut replies = Vec::new();
+        loop {
+            for (hdr, reply) in self.recv()? {
+                if hdr.seq != seq {
+                    continue;
+                }
+                match reply.msg_type {
+                    NLMSG_DONE => return Ok(replies),
+                    NLMSG_ERROR => {
+                        check_ack(function_name, &reply)?;
+                        return Ok(replies);
+                    }
+                    _ => replies.push(reply),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for NetlinkSocket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// The fields of a received netlink header that are relevant to the callers.
+pub(crate) struct NlMsgHdrInfo {
+    pub(crate) seq: u32,
+}
+
+fn check_ack(function_name: &str, reply: &NetlinkReply) -> Result<()> {
+    match from_bytes::<i32>(&reply.payload) {
+        Some(0) => Ok(()),
+        Some(err) => map_errno_error(function_name, -err),
+        None => map_errno_error(function_name, libc::EPROTO),
+    }
+}
@@ -0,0 +1,122 @@
+//! This module contains a handle on network namespaces, used to look up
+//! interfaces and attach programs in a namespace other than the one of the
+//! calling thread, i.e. the namespace of a container.
+//!
+//! Netlink operations use sockets opened in the target namespace, other
+//! operations run on a helper thread that enters the namespace with setns,
+//! leaving the namespace of the calling thread untouched.
+
+use crate::{
+    error::{Error, Result},
+    utils::*,
+};
+use libc;
+use std::{
+    fs::File,
+    mem,
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+const NSFS_MAGIC: i64 = 0x6e73_6673;
+const NETNS_RUN_DIR: &str = "/var/run/netns";
+
+/// An open network namespace.
+#[derive(Debug, Clone)]
+pub struct NetNs {
+    file: Arc<File>,
+    path: Option<PathBuf>,
+}
+
+impl NetNs {
+    /// Open the network namespace `path`, i.e. `/proc/<pid>/ns/net` or a
+    /// namespace bind mounted by `ip netns add`.
+    #[named]
+    pub fn from_path(path: &Path) -> Result<NetNs> {
+        let file = File::open(path).map_err(map_io_error(function_name!()))?;
+        let netns = NetNs {
+            file: Arc::new(file),
+            path: Some(path.to_owned()),
+        };
+        netns.check()?;
+        Ok(netns)
+    }
+
+    /// Open the network namespace `name` created by `ip netns add`.
+    pub fn from_name(name: &str) -> Result<NetNs> {
+        NetNs::from_path(&Path::new(NETNS_RUN_DIR).join(name))
+    }
+
+    /// Open the network namespace referred by `fd`, which is duplicated.
+    #[named]
+    pub fn from_fd(fd: RawFd) -> Result<NetNs> {
+        let dup_fd = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+        if dup_fd < 0 {
+            return map_sys_error(function_name!());
+        }
+        let netns = NetNs {
+            file: Arc::new(unsafe { File::from_raw_fd(dup_fd) }),
+            path: None,
+        };
+        netns.check()?;
+        Ok(netns)
+    }
+
+    /// Open the network namespace of the calling thread.
+    pub fn current() -> Result<NetNs> {
+        NetNs::from_path(Path::new("/proc/thread-self/ns/net"))
+    }
+
+    /// The path the namespace was opened from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    #[named]
+    fn check(&self) -> Result<()> {
+        let mut stat: libc::statfs = unsafe { mem::zeroed() };
+        if unsafe { libc::fstatfs(self.as_raw_fd(), &mut stat) } < 0 {
+            return map_sys_error(function_name!());
+        }
+        if stat.f_type as i64 != NSFS_MAGIC {
+            return Err(Error::Custom(format!(
+                "{} is not a namespace",
+                self.path
+                    .as_ref()
+                    .map_or("fd".to_owned(), |p| p.display().to_string())
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run `f` on a helper thread entered in the namespace, and returns its result.
+    #[named]
+    pub fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        // The namespace fd outlives the thread, joined before returning.
+        let fd = self.as_raw_fd();
+        let handle = thread::spawn(move || {
+            if unsafe { libc::setns(fd, libc::CLONE_NEWNET) } < 0 {
+                return map_sys_error(function_name!());
+            }
+            Ok(f())
+        });
+        match handle.join() {
+            Ok(r) => r,
+            Err(_) => Err(Error::Custom(
+                "The network namespace helper thread panicked".to_owned(),
+            )),
+        }
+    }
+}
+
+impl AsRawFd for NetNs {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
@@ -0,0 +1,106 @@
+//! This module contains helpers to create scratch cgroups and run processes
+//! in them, i.e. to test programs attached with `userspace::cgroup`.
+
+use crate::{
+    error::Result,
+    userspace::cgroup::{self, Cgroup},
+    utils::*,
+};
+use libc;
+use std::{
+    fs,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static SCRATCH_CGROUP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A cgroup created at the root of the cgroup v2 hierarchy, deleted when dropped.
+pub struct ScratchCgroup {
+    cgroup: Cgroup,
+    parent: PathBuf,
+}
+
+impl ScratchCgroup {
+    /// Create a cgroup whose name starts with `prefix` and is unique in the system.
+    #[named]
+    pub fn new(prefix: &str) -> Result<ScratchCgroup> {
+        let parent = cgroup::cgroup2_mount()?;
+        let path = parent.join(format!(
+            "{}-{}-{}",
+            prefix,
+            std::process::id(),
+            SCRATCH_CGROUP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir(&path).map_err(map_io_error(function_name!()))?;
+        match Cgroup::open(&path) {
+            Ok(cgroup) => Ok(ScratchCgroup { cgroup, parent }),
+            Err(e) => {
+                let _ = fs::remove_dir(&path);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn cgroup(&self) -> &Cgroup {
+        &self.cgroup
+    }
+
+    pub fn path(&self) -> &Path {
+        self.cgroup.path()
+    }
+
+    /// Move the process `pid` into the cgroup.
+    #[named]
+    pub fn add_process(&self, pid: u32) -> Result<()> {
+        fs::write(self.path().join("cgroup.procs"), pid.to_string())
+            .map_err(map_io_error(function_name!()))
+    }
+
+    /// Returns the processes of the cgroup.
+    #[named]
+    pub fn processes(&self) -> Result<Vec<u32>> {
+        let procs = fs::read_to_string(self.path().join("cgroup.procs"))
+            .map_err(map_io_error(function_name!()))?;
+        Ok(procs.lines().filter_map(|pid| pid.trim().parse().ok()).collect())
+    }
+
+    /// Spawn `command` as a child process running in the cgroup from its start.
+    #[named]
+    pub fn spawn(&self, command: &mut Command) -> Result<Child> {
+        let procs = str_to_cstring(path_to_str(&self.path().join("cgroup.procs"))?)?;
+        // Only async-signal-safe functions can be called between fork and exec.
+        let join_cgroup = move || {
+            let fd = unsafe { libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // Writing 0 moves the writing process.
+            let written = unsafe { libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) };
+            let error = std::io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            if written < 0 {
+                return Err(error);
+            }
+            Ok(())
+        };
+        unsafe { command.pre_exec(join_cgroup) }
+            .spawn()
+            .map_err(map_io_error(function_name!()))
+    }
+}
+
+impl Drop for ScratchCgroup {
+    fn drop(&mut self) {
+        // A cgroup can only be removed once empty.
+        if let Ok(pids) = self.processes() {
+            let parent_procs = self.parent.join("cgroup.procs");
+            for pid in pids {
+                let _ = fs::write(&parent_procs, pid.to_string());
+            }
+        }
+        let _ = fs::remove_dir(self.path());
+    }
+}
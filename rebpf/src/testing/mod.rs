@@ -0,0 +1,7 @@
+//! This module contains helpers to set up and tear down the system resources
+//! needed by tests of bpf programs. They usually require root privileges.
+//!
+//! Requires the `testing` feature.
+
+pub mod cgroup;
+pub mod netns;
@@ -0,0 +1,188 @@
+//! This module contains helpers to build network topologies in scratch
+//! network namespaces, connected by veth pairs, i.e. to drive traffic through
+//! XDP programs attached in `XdpFlags::SKB_MODE` or TC programs without a
+//! physical network.
+//!
+//! Example :
+//!
+//! ```no_run
+//! use rebpf::testing::netns::{add_ipv4_address, ScratchNetNs, VethPair};
+//! use std::net::Ipv4Addr;
+//!
+//! # fn main() -> rebpf::error::Result<()> {
+//! let client = ScratchNetNs::new()?;
+//! let server = ScratchNetNs::new()?;
+//! let veth = VethPair::new(client.netns(), "veth0", server.netns(), "veth0")?;
+//! add_ipv4_address(veth.left(), Ipv4Addr::new(10, 0, 0, 1), 24)?;
+//! add_ipv4_address(veth.right(), Ipv4Addr::new(10, 0, 0, 2), 24)?;
+//! // Attach programs to veth.right() and run clients with client.netns().run(...)
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    error::{Error, Result},
+    interface::{self, Interface},
+    netlink::{self, IfAddrMsg, IfInfoMsg, NetlinkMessage, NetlinkSocket},
+    netns::NetNs,
+    utils::*,
+};
+use libc;
+use std::{net::Ipv4Addr, os::unix::io::AsRawFd, thread};
+
+const VETH_INFO_PEER: u16 = 1;
+
+/// A fresh network namespace, only holding a loopback interface (up).
+///
+/// The namespace, and the interfaces it contains, are destroyed once the
+/// scratch namespace and every `Interface` or `NetNs` referring it are dropped.
+pub struct ScratchNetNs {
+    netns: NetNs,
+}
+
+impl ScratchNetNs {
+    #[named]
+    pub fn new() -> Result<ScratchNetNs> {
+        // The helper thread exits once the namespace is open: the namespace
+        // lives as long as the fd referring it.
+        let netns = thread::spawn(|| {
+            if unsafe { libc::unshare(libc::CLONE_NEWNET) } < 0 {
+                return map_sys_error(function_name!());
+            }
+            NetNs::current()
+        })
+        .join()
+        .map_err(|_| Error::Custom("The network namespace helper thread panicked".to_owned()))??;
+        let scratch = ScratchNetNs { netns };
+        set_up(&scratch.interface("lo")?)?;
+        Ok(scratch)
+    }
+
+    pub fn netns(&self) -> &NetNs {
+        &self.netns
+    }
+
+    /// Look up the interface `name` of the namespace.
+    pub fn interface(&self, name: &str) -> Result<Interface> {
+        interface::get_interface_in(&self.netns, name)
+    }
+
+    pub fn interfaces(&self) -> Result<Vec<Interface>> {
+        interface::interfaces_in(&self.netns)
+    }
+}
+
+/// A pair of connected veth interfaces, possibly in different namespaces.
+/// Deleted when dropped.
+pub struct VethPair {
+    left: Interface,
+    right: Interface,
+}
+
+impl VethPair {
+    /// Create a veth pair made of `left_name` in `left_netns` and `right_name`
+    /// in `right_netns`, and set both ends up.
+    #[named]
+    pub fn new(
+        left_netns: &NetNs,
+        left_name: &str,
+        right_netns: &NetNs,
+        right_name: &str,
+    ) -> Result<VethPair> {
+        let mut socket = NetlinkSocket::new()?;
+        let mut msg = NetlinkMessage::new(
+            netlink::RTM_NEWLINK,
+            netlink::NLM_F_CREATE | netlink::NLM_F_EXCL,
+            &IfInfoMsg::default(),
+        );
+        msg.push_attr_str(netlink::IFLA_IFNAME, left_name);
+        msg.push_attr_u32(netlink::IFLA_NET_NS_FD, left_netns.as_raw_fd() as u32);
+        msg.begin_nested(netlink::IFLA_LINKINFO);
+        msg.push_attr_str(netlink::IFLA_INFO_KIND, "veth");
+        msg.begin_nested(netlink::IFLA_INFO_DATA);
+        msg.begin_nested(VETH_INFO_PEER);
+        msg.push_header(&IfInfoMsg::default());
+        msg.push_attr_str(netlink::IFLA_IFNAME, right_name);
+        msg.push_attr_u32(netlink::IFLA_NET_NS_FD, right_netns.as_raw_fd() as u32);
+        msg.end_nested();
+        msg.end_nested();
+        msg.end_nested();
+        socket.request(function_name!(), msg)?;
+
+        let left = interface::get_interface_in(left_netns, left_name)?;
+        let right = match interface::get_interface_in(right_netns, right_name) {
+            Ok(right) => right,
+            Err(e) => {
+                let _ = delete_link(&left);
+                return Err(e);
+            }
+        };
+        // Dropping the pair from now on deletes both ends.
+        let mut pair = VethPair { left, right };
+        set_up(&pair.left)?;
+        pair.left.flags |= libc::IFF_UP as u32;
+        set_up(&pair.right)?;
+        pair.right.flags |= libc::IFF_UP as u32;
+        Ok(pair)
+    }
+
+    pub fn left(&self) -> &Interface {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Interface {
+        &self.right
+    }
+}
+
+impl Drop for VethPair {
+    fn drop(&mut self) {
+        // Deleting one end deletes its peer.
+        let _ = delete_link(&self.left);
+    }
+}
+
+/// Set `interface` up.
+#[named]
+pub fn set_up(interface: &Interface) -> Result<()> {
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let ifi = IfInfoMsg {
+        ifi_index: interface.ifindex() as i32,
+        ifi_flags: libc::IFF_UP as u32,
+        ifi_change: libc::IFF_UP as u32,
+        ..Default::default()
+    };
+    let msg = NetlinkMessage::new(netlink::RTM_NEWLINK, 0, &ifi);
+    socket.request(function_name!(), msg)
+}
+
+/// Add the address `addr`/`prefix_len` to `interface`.
+#[named]
+pub fn add_ipv4_address(interface: &Interface, addr: Ipv4Addr, prefix_len: u8) -> Result<()> {
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let ifa = IfAddrMsg {
+        ifa_family: libc::AF_INET as u8,
+        ifa_prefixlen: prefix_len,
+        ifa_index: interface.ifindex(),
+        ..Default::default()
+    };
+    let mut msg = NetlinkMessage::new(
+        netlink::RTM_NEWADDR,
+        netlink::NLM_F_CREATE | netlink::NLM_F_EXCL,
+        &ifa,
+    );
+    msg.push_attr(netlink::IFA_LOCAL, &addr.octets());
+    msg.push_attr(netlink::IFA_ADDRESS, &addr.octets());
+    socket.request(function_name!(), msg)
+}
+
+#[named]
+fn delete_link(interface: &Interface) -> Result<()> {
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let ifi = IfInfoMsg {
+        ifi_index: interface.ifindex() as i32,
+        ..Default::default()
+    };
+    let msg = NetlinkMessage::new(netlink::RTM_DELLINK, 0, &ifi);
+    socket.request(function_name!(), msg)
+}
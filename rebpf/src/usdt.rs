@@ -0,0 +1,51 @@
+//! This module contains the definitions shared by the kernel side
+//! ([`bpf::usdt`]) and the userspace side ([`userspace::usdt`]) of the USDT
+//! (statically defined tracepoints) support.
+//!
+//! Userspace decodes the argument specifications of the probes from the
+//! `.note.stapsdt` ELF notes and stores them, keyed by probe address, in the
+//! map declared by the [`usdt_specs_map`] macro. The kernel side uses them to
+//! read the probe arguments from the registers of the traced task.
+//!
+//! [`bpf::usdt`]: ../bpf/usdt/index.html
+//! [`userspace::usdt`]: ../userspace/usdt/index.html
+//! [`usdt_specs_map`]: ../macro.usdt_specs_map.html
+
+/// Maximum number of arguments of a USDT probe.
+pub const USDT_MAX_ARGS: usize = 12;
+
+/// Maximum number of probe addresses in the specifications map.
+pub const MAX_USDT_SPECS: u32 = 256;
+
+/// Name of the `HashMap<u64, UsdtSpec>` map holding the probe specifications.
+pub const USDT_SPECS_MAP: &str = "usdt_specs";
+
+/// The argument is a constant, stored in `value`.
+pub const USDT_ARG_CONST: u32 = 0;
+/// The argument is stored in the register at `reg_offset` in `pt_regs`.
+pub const USDT_ARG_REG: u32 = 1;
+/// The argument is stored in memory, at the address contained in the register
+/// at `reg_offset` plus `value`.
+pub const USDT_ARG_REG_DEREF: u32 = 2;
+
+/// Location and size of a USDT probe argument.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UsdtArgSpec {
+    /// One of the `USDT_ARG_*` constants.
+    pub kind: u32,
+    pub reg_offset: u32,
+    pub value: i64,
+    /// Size in bytes of the argument: 1, 2, 4 or 8.
+    pub size: u32,
+    /// Non-zero if the argument must be sign extended.
+    pub signed: u32,
+}
+
+/// Arguments of a USDT probe.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UsdtSpec {
+    pub args: [UsdtArgSpec; USDT_MAX_ARGS],
+    pub nr_args: u32,
+}
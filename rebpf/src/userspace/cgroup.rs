@@ -0,0 +1,192 @@
+//! This module contains high-level api to attach `CGROUP_SKB`, `CGROUP_SOCK`,
+//! `CGROUP_SOCK_ADDR`, `CGROUP_DEVICE`, `CGROUP_SYSCTL`, `CGROUP_SOCKOPT` and
+//! `SOCK_OPS` programs to cgroup v2 directories.
+//!
+//! A program attached to a cgroup applies to the tasks of the cgroup and of
+//! its descendants, depending on the `BpfAttachFlags` used at each level.
+
+use crate::{
+    error::{Error, Result},
+    libbpf::{self, BpfAttachFlags, BpfAttachType, BpfProgFd, BpfProgType},
+    utils::*,
+};
+use libc;
+use std::{
+    fs::{self, File},
+    mem,
+    os::unix::io::{AsRawFd, RawFd},
+    path::{Path, PathBuf},
+};
+
+const CGROUP2_SUPER_MAGIC: i64 = 0x6367_7270;
+
+/// The hooks of a cgroup where programs can be attached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum CgroupAttachType {
+    INET_INGRESS,
+    INET_EGRESS,
+    INET_SOCK_CREATE,
+    SOCK_OPS,
+    DEVICE,
+    INET4_BIND,
+    INET6_BIND,
+    INET4_CONNECT,
+    INET6_CONNECT,
+    INET4_POST_BIND,
+    INET6_POST_BIND,
+    UDP4_SENDMSG,
+    UDP6_SENDMSG,
+    UDP4_RECVMSG,
+    UDP6_RECVMSG,
+    SYSCTL,
+    GETSOCKOPT,
+    SETSOCKOPT,
+}
+
+impl CgroupAttachType {
+    pub fn attach_type(self) -> BpfAttachType {
+        match self {
+            CgroupAttachType::INET_INGRESS => BpfAttachType::CGROUP_INET_INGRESS,
+            CgroupAttachType::INET_EGRESS => BpfAttachType::CGROUP_INET_EGRESS,
+            CgroupAttachType::INET_SOCK_CREATE => BpfAttachType::CGROUP_INET_SOCK_CREATE,
+            CgroupAttachType::SOCK_OPS => BpfAttachType::CGROUP_SOCK_OPS,
+            CgroupAttachType::DEVICE => BpfAttachType::CGROUP_DEVICE,
+            CgroupAttachType::INET4_BIND => BpfAttachType::CGROUP_INET4_BIND,
+            CgroupAttachType::INET6_BIND => BpfAttachType::CGROUP_INET6_BIND,
+            CgroupAttachType::INET4_CONNECT => BpfAttachType::CGROUP_INET4_CONNECT,
+            CgroupAttachType::INET6_CONNECT => BpfAttachType::CGROUP_INET6_CONNECT,
+            CgroupAttachType::INET4_POST_BIND => BpfAttachType::CGROUP_INET4_POST_BIND,
+            CgroupAttachType::INET6_POST_BIND => BpfAttachType::CGROUP_INET6_POST_BIND,
+            CgroupAttachType::UDP4_SENDMSG => BpfAttachType::CGROUP_UDP4_SENDMSG,
+            CgroupAttachType::UDP6_SENDMSG => BpfAttachType::CGROUP_UDP6_SENDMSG,
+            CgroupAttachType::UDP4_RECVMSG => BpfAttachType::CGROUP_UDP4_RECVMSG,
+            CgroupAttachType::UDP6_RECVMSG => BpfAttachType::CGROUP_UDP6_RECVMSG,
+            CgroupAttachType::SYSCTL => BpfAttachType::CGROUP_SYSCTL,
+            CgroupAttachType::GETSOCKOPT => BpfAttachType::CGROUP_GETSOCKOPT,
+            CgroupAttachType::SETSOCKOPT => BpfAttachType::CGROUP_SETSOCKOPT,
+        }
+    }
+
+    /// The type of the programs that can be attached to this hook.
+    pub fn prog_type(self) -> BpfProgType {
+        match self {
+            CgroupAttachType::INET_INGRESS | CgroupAttachType::INET_EGRESS => {
+                BpfProgType::CGROUP_SKB
+            }
+            CgroupAttachType::INET_SOCK_CREATE
+            | CgroupAttachType::INET4_POST_BIND
+            | CgroupAttachType::INET6_POST_BIND => BpfProgType::CGROUP_SOCK,
+            CgroupAttachType::SOCK_OPS => BpfProgType::SOCK_OPS,
+            CgroupAttachType::DEVICE => BpfProgType::CGROUP_DEVICE,
+            CgroupAttachType::INET4_BIND
+            | CgroupAttachType::INET6_BIND
+            | CgroupAttachType::INET4_CONNECT
+            | CgroupAttachType::INET6_CONNECT
+            | CgroupAttachType::UDP4_SENDMSG
+            | CgroupAttachType::UDP6_SENDMSG
+            | CgroupAttachType::UDP4_RECVMSG
+            | CgroupAttachType::UDP6_RECVMSG => BpfProgType::CGROUP_SOCK_ADDR,
+            CgroupAttachType::SYSCTL => BpfProgType::CGROUP_SYSCTL,
+            CgroupAttachType::GETSOCKOPT | CgroupAttachType::SETSOCKOPT => {
+                BpfProgType::CGROUP_SOCKOPT
+            }
+        }
+    }
+}
+
+/// The programs attached to a hook of a cgroup.
+#[derive(Debug, Clone)]
+pub struct CgroupProgs {
+    /// The flags the programs were attached with, None if no program is attached.
+    pub flags: Option<BpfAttachFlags>,
+    pub prog_ids: Vec<u32>,
+}
+
+/// An open cgroup v2 directory.
+pub struct Cgroup {
+    file: File,
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Open the cgroup v2 directory `path`, i.e. `/sys/fs/cgroup/my_service`.
+    #[named]
+    pub fn open(path: &Path) -> Result<Cgroup> {
+        let file = File::open(path).map_err(map_io_error(function_name!()))?;
+        let mut stat: libc::statfs = unsafe { mem::zeroed() };
+        if unsafe { libc::fstatfs(file.as_raw_fd(), &mut stat) } < 0 {
+            return map_sys_error(function_name!());
+        }
+        if stat.f_type as i64 != CGROUP2_SUPER_MAGIC {
+            return Err(Error::Custom(format!(
+                "{} is not a cgroup v2 directory",
+                path.display()
+            )));
+        }
+        Ok(Cgroup {
+            file,
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Attach `bpf_fd` to the hook `attach_type` of the cgroup.
+    ///
+    /// With `BpfAttachFlags::ALLOW_MULTI`, the program is added to the programs
+    /// already attached, otherwise it replaces them. The flags must be the same
+    /// as the ones of the programs already attached.
+    pub fn attach(
+        &self,
+        bpf_fd: &BpfProgFd,
+        attach_type: CgroupAttachType,
+        flags: BpfAttachFlags,
+    ) -> Result<()> {
+        libbpf::bpf_prog_attach(bpf_fd, self.as_raw_fd(), attach_type.attach_type(), flags)
+    }
+
+    /// Detach `bpf_fd` from the hook `attach_type` of the cgroup.
+    pub fn detach(&self, bpf_fd: &BpfProgFd, attach_type: CgroupAttachType) -> Result<()> {
+        libbpf::bpf_prog_detach2(bpf_fd, self.as_raw_fd(), attach_type.attach_type())
+    }
+
+    /// Query the programs attached to the hook `attach_type` of the cgroup itself,
+    /// not including the ones inherited from its ancestors.
+    pub fn query(&self, attach_type: CgroupAttachType) -> Result<CgroupProgs> {
+        let (flags, prog_ids) =
+            libbpf::bpf_prog_query(self.as_raw_fd(), attach_type.attach_type(), 0)?;
+        let flags = if prog_ids.is_empty() {
+            None
+        } else {
+            BpfAttachFlags::from_u32(flags)
+        };
+        Ok(CgroupProgs { flags, prog_ids })
+    }
+}
+
+impl AsRawFd for Cgroup {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+/// Returns the mount point of the cgroup v2 hierarchy.
+#[named]
+pub fn cgroup2_mount() -> Result<PathBuf> {
+    let mounts = fs::read_to_string("/proc/self/mounts").map_err(map_io_error(function_name!()))?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() >= 3 && fields[2] == "cgroup2" {
+                Some(PathBuf::from(fields[1]))
+            } else {
+                None
+            }
+        })
+        .next()
+        .ok_or_else(|| Error::Custom("The cgroup v2 hierarchy is not mounted".to_owned()))
+}
@@ -0,0 +1,201 @@
+//! This module contains the userspace side of the XDP dispatcher,
+//! see [`dispatcher`] for an overview.
+//!
+//! [`dispatcher`]: ../../dispatcher/index.html
+
+use crate::{
+    dispatcher::{
+        DispatcherSlot, DISPATCHER_CURSOR_MAP, DISPATCHER_PROGS_MAP, DISPATCHER_SLOTS_MAP,
+        MAX_DISPATCHER_PROGS,
+    },
+    error::{Error, Result},
+    interface::Interface,
+    libbpf::{
+        self, BpfFd, BpfObject, BpfProgFd, BpfProgType, BpfUpdateElemFlags, OwnedBpfFd, XdpAction,
+        XdpFlags,
+    },
+    userspace::{
+        maps::{Array, Map, PerCpuArray, ProgArray, Update},
+        xdp,
+    },
+};
+use std::path::Path;
+
+struct XdpComponent {
+    prog_fd: OwnedBpfFd<BpfProgFd>,
+    prog_id: u32,
+    priority: u32,
+    chain_call_actions: u32,
+}
+
+/// Manager of the component programs of an XDP dispatcher.
+///
+/// Components are called in increasing priority order. Components with the
+/// same priority are called in insertion order.
+///
+/// The kernel empties the program array of the chain once its last userspace
+/// reference is closed: dropping the dispatcher while the root program is
+/// attached leaves a root without components, passing every packet. Detach
+/// the root program before dropping the dispatcher.
+pub struct XdpDispatcher {
+    bpf_object: BpfObject,
+    root_fd: BpfProgFd,
+    progs: ProgArray,
+    slots: Array<DispatcherSlot>,
+    cursor: PerCpuArray<u32>,
+    components: Vec<XdpComponent>,
+}
+
+impl XdpDispatcher {
+    /// Load the root program of the dispatcher, with title `prog_title`, from the
+    /// bpf object at `file_path`. The object must declare the dispatcher maps.
+    pub fn load(file_path: &Path, prog_title: &str) -> Result<XdpDispatcher> {
+        let (bpf_object, _) = libbpf::bpf_prog_load(file_path, BpfProgType::XDP)?;
+        let root_prog = libbpf::bpf_object__find_program_by_title(&bpf_object, prog_title)?;
+        let root_fd = libbpf::bpf_program__fd(&root_prog)?;
+        let progs = ProgArray::from_obj(&bpf_object, DISPATCHER_PROGS_MAP)?;
+        let slots = Array::<DispatcherSlot>::from_obj(&bpf_object, DISPATCHER_SLOTS_MAP)?;
+        let cursor = PerCpuArray::<u32>::from_obj(&bpf_object, DISPATCHER_CURSOR_MAP)?;
+        progs.extract_info()?;
+        slots.extract_info()?;
+        cursor.extract_info()?;
+
+        Ok(XdpDispatcher {
+            bpf_object,
+            root_fd,
+            progs,
+            slots,
+            cursor,
+            components: Vec::new(),
+        })
+    }
+
+    pub fn root_prog(&self) -> &BpfProgFd {
+        &self.root_fd
+    }
+
+    pub fn attach(&self, interface: &Interface, xdp_flags: XdpFlags) -> Result<()> {
+        libbpf::bpf_set_link_xdp_fd(interface, Some(&self.root_fd), xdp_flags)
+    }
+
+    /// Detach the root program from `interface`, checking that it is still attached.
+    pub fn detach(&self, interface: &Interface, xdp_flags: XdpFlags) -> Result<()> {
+        let root_id = libbpf::bpf_obj_get_info_by_fd(&self.root_fd)?.id();
+        xdp::detach_prog(interface, root_id, xdp_flags)
+    }
+
+    /// Load a bpf object containing component programs, sharing the
+    /// dispatcher maps of the root program.
+    pub fn load_component_object(&self, file_path: &Path) -> Result<BpfObject> {
+        xdp::load_reusing_maps(
+            file_path,
+            BpfProgType::XDP,
+            &[
+                (DISPATCHER_PROGS_MAP, self.progs.fd()),
+                (DISPATCHER_SLOTS_MAP, self.slots.fd()),
+                (DISPATCHER_CURSOR_MAP, self.cursor.fd()),
+            ],
+        )
+    }
+
+    /// Add a component program to the chain. The chain continues after it only
+    /// if it returns one of the `chain_call_actions`.
+    ///
+    /// The dispatcher keeps its own reference to the program, so the bpf
+    /// object defining it can be dropped.
+    ///
+    /// Returns the id of the program, used to remove it.
+    pub fn add(
+        &mut self,
+        prog_fd: &BpfProgFd,
+        priority: u32,
+        chain_call_actions: &[XdpAction],
+    ) -> Result<u32> {
+        if self.components.len() as u32 >= MAX_DISPATCHER_PROGS {
+            return Err(Error::Custom(format!(
+                "The dispatcher can't hold more than {} programs",
+                MAX_DISPATCHER_PROGS
+            )));
+        }
+        let prog_id = libbpf::bpf_obj_get_info_by_fd(prog_fd)?.id();
+        if self.components.iter().any(|c| c.prog_id == prog_id) {
+            return Err(Error::Custom(format!(
+                "The program {} is already part of the dispatcher",
+                prog_id
+            )));
+        }
+        let chain_call_actions = chain_call_actions
+            .iter()
+            .fold(0, |mask, action| mask | 1 << *action as u32);
+        let position = self
+            .components
+            .iter()
+            .position(|c| c.priority > priority)
+            .unwrap_or(self.components.len());
+        let prog_fd = libbpf::bpf_prog_get_fd_by_id(prog_id)?;
+        self.components.insert(
+            position,
+            XdpComponent {
+                prog_fd,
+                prog_id,
+                priority,
+                chain_call_actions,
+            },
+        );
+        if let Err(e) = self.sync(position) {
+            // Restore the previous chain, which ends before the added slot.
+            self.components.remove(position);
+            let _ = self.sync(position);
+            let _ = libbpf::bpf_map_delete_elem(self.progs.fd(), &(self.components.len() as u32));
+            return Err(e);
+        }
+
+        Ok(prog_id)
+    }
+
+    /// Remove the component program with id `prog_id` from the chain.
+    pub fn remove(&mut self, prog_id: u32) -> Result<()> {
+        let position = match self.components.iter().position(|c| c.prog_id == prog_id) {
+            Some(position) => position,
+            None => {
+                return Err(Error::Custom(format!(
+                    "The program {} is not part of the dispatcher",
+                    prog_id
+                )))
+            }
+        };
+        let component = self.components.remove(position);
+        if let Err(e) = self.sync(position) {
+            // Restore the previous chain, whose last slot hasn't been touched.
+            self.components.insert(position, component);
+            let _ = self.sync(position);
+            return Err(e);
+        }
+        libbpf::bpf_map_delete_elem(self.progs.fd(), &(self.components.len() as u32))
+    }
+
+    /// Returns the ids and the priorities of the component programs, in chain order.
+    pub fn components(&self) -> Vec<(u32, u32)> {
+        self.components
+            .iter()
+            .map(|c| (c.prog_id, c.priority))
+            .collect()
+    }
+
+    /// Rewrite the dispatcher slots from `from` to the end of the chain.
+    ///
+    /// The slots are updated one by one while the chain may be running, so a
+    /// packet can be processed by a chain mixing the old and the new order.
+    fn sync(&mut self, from: usize) -> Result<()> {
+        for (index, component) in self.components.iter().enumerate().skip(from) {
+            let index = index as u32;
+            let slot = DispatcherSlot {
+                chain_call_actions: component.chain_call_actions,
+            };
+            self.slots.update(&index, &slot, BpfUpdateElemFlags::ANY)?;
+            self.progs
+                .update(&index, &(component.prog_fd.fd() as u32), BpfUpdateElemFlags::ANY)?;
+        }
+        Ok(())
+    }
+}
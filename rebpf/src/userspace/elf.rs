@@ -0,0 +1,388 @@
+//! This module contains a minimal ELF64 reader, used to resolve the file
+//! offsets of the symbols and notes of the binaries probed from userspace.
+
+use crate::{
+    error::{Error, Result},
+    utils::*,
+};
+use std::{fs, path::Path};
+
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOTE: u32 = 7;
+const SHT_DYNSYM: u32 = 11;
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+
+pub(crate) struct Section {
+    pub(crate) name: String,
+    pub(crate) type_: u32,
+    pub(crate) addr: u64,
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+    link: u32,
+    entsize: u64,
+}
+
+struct Segment {
+    type_: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+pub(crate) struct Symbol {
+    pub(crate) name: String,
+    pub(crate) value: u64,
+    pub(crate) size: u64,
+    pub(crate) is_func: bool,
+}
+
+/// A note of a SHT_NOTE section.
+pub(crate) struct Note<'a> {
+    pub(crate) name: &'a [u8],
+    pub(crate) type_: u32,
+    pub(crate) desc: &'a [u8],
+}
+
+pub(crate) struct Elf {
+    data: Vec<u8>,
+    big_endian: bool,
+    pub(crate) is_shared: bool,
+    sections: Vec<Section>,
+    segments: Vec<Segment>,
+}
+
+fn invalid_elf<T>(path: &str) -> Result<T> {
+    Err(Error::Custom(format!("Invalid or unsupported ELF file: {}", path)))
+}
+
+impl Elf {
+    #[named]
+    pub(crate) fn open(path: &Path) -> Result<Elf> {
+        let path_s = path_to_str(path)?;
+        let data = fs::read(path).map_err(map_io_error(function_name!()))?;
+        match Elf::parse(data) {
+            Some(elf) => Ok(elf),
+            None => invalid_elf(path_s),
+        }
+    }
+
+    fn parse(data: Vec<u8>) -> Option<Elf> {
+        if data.len() < 64 || &data[..4] != b"\x7fELF" || data[4] != ELFCLASS64 {
+            return None;
+        }
+        let big_endian = match data[5] {
+            ELFDATA2LSB => false,
+            ELFDATA2MSB => true,
+            _ => return None,
+        };
+        let mut elf = Elf {
+            data,
+            big_endian,
+            is_shared: false,
+            sections: Vec::new(),
+            segments: Vec::new(),
+        };
+        // ET_DYN: shared library or position independent executable.
+        elf.is_shared = elf.u16_at(16)? == 3;
+        let phoff = elf.u64_at(32)? as usize;
+        let shoff = elf.u64_at(40)? as usize;
+        let phentsize = elf.u16_at(54)? as usize;
+        let phnum = elf.u16_at(56)? as usize;
+        let shentsize = elf.u16_at(58)? as usize;
+        let shnum = elf.u16_at(60)? as usize;
+        let shstrndx = elf.u16_at(62)? as usize;
+
+        for i in 0..phnum {
+            let ph = phoff.checked_add(i.checked_mul(phentsize)?)?;
+            elf.segments.push(Segment {
+                type_: elf.u32_at(ph)?,
+                offset: elf.u64_at(ph + 8)?,
+                vaddr: elf.u64_at(ph + 16)?,
+                filesz: elf.u64_at(ph + 32)?,
+            });
+        }
+
+        let mut name_offsets = Vec::with_capacity(shnum);
+        for i in 0..shnum {
+            let sh = shoff.checked_add(i.checked_mul(shentsize)?)?;
+            name_offsets.push(elf.u32_at(sh)? as usize);
+            elf.sections.push(Section {
+                name: String::new(),
+                type_: elf.u32_at(sh + 4)?,
+                addr: elf.u64_at(sh + 16)?,
+                offset: elf.u64_at(sh + 24)?,
+                size: elf.u64_at(sh + 32)?,
+                link: elf.u32_at(sh + 40)?,
+                entsize: elf.u64_at(sh + 56)?,
+            });
+        }
+        if shstrndx < elf.sections.len() {
+            let shstrtab_offset = elf.sections[shstrndx].offset as usize;
+            for (i, name_offset) in name_offsets.into_iter().enumerate() {
+                let name = elf.str_at(shstrtab_offset.checked_add(name_offset)?)?;
+                elf.sections[i].name = name;
+            }
+        }
+        Some(elf)
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Option<&[u8]> {
+        self.data.get(offset..offset.checked_add(len)?)
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let b = self.bytes(offset, 2)?;
+        let v = [b[0], b[1]];
+        Some(if self.big_endian {
+            u16::from_be_bytes(v)
+        } else {
+            u16::from_le_bytes(v)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let b = self.bytes(offset, 4)?;
+        let v = [b[0], b[1], b[2], b[3]];
+        Some(if self.big_endian {
+            u32::from_be_bytes(v)
+        } else {
+            u32::from_le_bytes(v)
+        })
+    }
+
+    fn u64_at(&self, offset: usize) -> Option<u64> {
+        let b = self.bytes(offset, 8)?;
+        let mut v = [0u8; 8];
+        v.copy_from_slice(b);
+        Some(if self.big_endian {
+            u64::from_be_bytes(v)
+        } else {
+            u64::from_le_bytes(v)
+        })
+    }
+
+    /// Reads the NUL-terminated string at `offset`.
+    fn str_at(&self, offset: usize) -> Option<String> {
+        let bytes = self.data.get(offset..)?;
+        let end = bytes.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+
+    pub(crate) fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    pub(crate) fn section_data(&self, section: &Section) -> Option<&[u8]> {
+        self.bytes(section.offset as usize, section.size as usize)
+    }
+
+    /// Returns the defined symbols of the static and dynamic symbol tables.
+    pub(crate) fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols = Vec::new();
+        for section in &self.sections {
+            if section.type_ != SHT_SYMTAB && section.type_ != SHT_DYNSYM {
+                continue;
+            }
+            let strtab = match self.sections.get(section.link as usize) {
+                Some(strtab) => strtab.offset as usize,
+                None => continue,
+            };
+            let entsize = if section.entsize == 0 {
+                24
+            } else {
+                section.entsize as usize
+            };
+            let count = section.size as usize / entsize;
+            for i in 0..count {
+                let parsed = (|| {
+                    let sym = (section.offset as usize).checked_add(i * entsize)?;
+                    let name_offset = self.u32_at(sym)? as usize;
+                    let info = *self.data.get(sym + 4)?;
+                    let shndx = self.u16_at(sym + 6)?;
+                    let value = self.u64_at(sym + 8)?;
+                    let size = self.u64_at(sym + 16)?;
+                    if shndx == SHN_UNDEF || name_offset == 0 {
+                        return None;
+                    }
+                    Some(Symbol {
+                        name: self.str_at(strtab.checked_add(name_offset)?)?,
+                        value,
+                        size,
+                        is_func: info & 0xf == STT_FUNC,
+                    })
+                })();
+                if let Some(symbol) = parsed {
+                    symbols.push(symbol);
+                }
+            }
+        }
+        symbols
+    }
+
+    /// Converts a virtual address into an offset in the file.
+    pub(crate) fn vaddr_to_offset(&self, vaddr: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|s| s.type_ == PT_LOAD && s.vaddr <= vaddr && vaddr - s.vaddr < s.filesz)
+            .map(|s| (vaddr - s.vaddr).checked_add(s.offset))
+            .or_else(|| {
+                self.sections
+                    .iter()
+                    .find(|s| s.addr != 0 && s.addr <= vaddr && vaddr - s.addr < s.size)
+                    .map(|s| (vaddr - s.addr).checked_add(s.offset))
+            })?
+    }
+
+    /// Returns the notes of the SHT_NOTE section `name`.
+    pub(crate) fn notes(&self, name: &str) -> Vec<Note<'_>> {
+        let mut notes = Vec::new();
+        let section = match self.section(name) {
+            Some(section) if section.type_ == SHT_NOTE => section,
+            _ => return notes,
+        };
+        let data = match self.section_data(section) {
+            Some(data) => data,
+            None => return notes,
+        };
+        let align4 = |v: usize| (v + 3) & !3;
+        let mut offset = 0;
+        while offset + 12 <= data.len() {
+            let base = section.offset as usize + offset;
+            let (namesz, descsz, type_) = match (
+                self.u32_at(base),
+                self.u32_at(base + 4),
+                self.u32_at(base + 8),
+            ) {
+                (Some(n), Some(d), Some(t)) => (n as usize, d as usize, t),
+                _ => break,
+            };
+            let name_begin = offset + 12;
+            let desc_begin = match name_begin.checked_add(namesz).and_then(|v| v.checked_add(3)) {
+                Some(v) => v & !3,
+                None => break,
+            };
+            let desc_end = match desc_begin.checked_add(descsz) {
+                Some(desc_end) if desc_end <= data.len() => desc_end,
+                _ => break,
+            };
+            // The name includes its NUL terminator.
+            let name_end = name_begin + namesz.saturating_sub(1);
+            notes.push(Note {
+                name: &data[name_begin..name_end],
+                type_,
+                desc: &data[desc_begin..desc_end],
+            });
+            offset = align4(desc_end);
+        }
+        notes
+    }
+
+    pub(crate) fn read_u64(&self, buf: &[u8]) -> Option<u64> {
+        let mut v = [0u8; 8];
+        v.copy_from_slice(buf.get(..8)?);
+        Some(if self.big_endian {
+            u64::from_be_bytes(v)
+        } else {
+            u64::from_le_bytes(v)
+        })
+    }
+}
+
+/// Returns the demangled form of a Rust or C++ symbol, without the Rust hash
+/// suffix, or None if the symbol isn't mangled.
+pub(crate) fn demangle(name: &str) -> Option<String> {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        return Some(format!("{:#}", demangled));
+    }
+    if name.starts_with("_Z") {
+        if let Ok(symbol) = cpp_demangle::Symbol::new(name) {
+            return Some(symbol.to_string());
+        }
+    }
+    None
+}
+
+/// Returns true if the symbol `name` is `wanted`, either as is or demangled.
+/// The parameters of demangled C++ functions are optional.
+pub(crate) fn symbol_matches(name: &str, wanted: &str) -> bool {
+    if name == wanted {
+        return true;
+    }
+    match demangle(name) {
+        Some(demangled) => {
+            demangled == wanted
+                || demangled
+                    .find('(')
+                    .is_some_and(|end| &demangled[..end] == wanted)
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[no_mangle]
+    #[inline(never)]
+    pub extern "C" fn rebpf_elf_test_function() -> u32 {
+        42
+    }
+
+    #[test]
+    fn demangle_rust() {
+        assert_eq!(
+            demangle("_ZN4core3fmt5write17h01234567890abcdeE").as_deref(),
+            Some("core::fmt::write")
+        );
+        assert_eq!(demangle("main"), None);
+    }
+
+    #[test]
+    fn demangle_cpp() {
+        assert_eq!(
+            demangle("_ZN3foo3barEi").as_deref(),
+            Some("foo::bar(int)")
+        );
+    }
+
+    #[test]
+    fn symbol_matching() {
+        assert!(symbol_matches("malloc", "malloc"));
+        assert!(symbol_matches("_ZN4core3fmt5write17h01234567890abcdeE", "core::fmt::write"));
+        assert!(symbol_matches("_ZN3foo3barEi", "foo::bar"));
+        assert!(symbol_matches("_ZN3foo3barEi", "foo::bar(int)"));
+        assert!(!symbol_matches("_ZN3foo3barEi", "foo::baz"));
+    }
+
+    #[test]
+    fn reject_overflowing_headers() {
+        let mut data = vec![0u8; 64];
+        data[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        data[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        data[54..56].copy_from_slice(&56u16.to_le_bytes());
+        data[56..58].copy_from_slice(&2u16.to_le_bytes());
+        assert!(Elf::parse(data).is_none());
+    }
+
+    #[test]
+    fn resolve_own_symbol() {
+        assert_eq!(rebpf_elf_test_function(), 42);
+        let elf = Elf::open(&std::env::current_exe().unwrap()).unwrap();
+        let symbol = elf
+            .symbols()
+            .into_iter()
+            .find(|s| s.name == "rebpf_elf_test_function")
+            .unwrap();
+        assert!(symbol.is_func);
+        let offset = elf.vaddr_to_offset(symbol.value).unwrap();
+        assert!(elf.section(".text").is_some());
+        assert!(offset > 0);
+    }
+}
@@ -0,0 +1,35 @@
+//! This module contains high-level api to attach `FLOW_DISSECTOR` programs
+//! to the network namespace of the calling thread (Linux 4.20+).
+//!
+//! The program replaces the built-in flow dissector of the kernel, used to
+//! hash packets for RPS, ECMP or fq for instance. A single program can be
+//! attached per network namespace.
+
+use crate::{
+    error::Result,
+    libbpf::{self, BpfAttachFlags, BpfAttachType, BpfProgFd},
+    utils::*,
+};
+use std::{fs::File, os::unix::io::AsRawFd};
+
+/// Attach `bpf_fd` to the current network namespace, replacing the program
+/// already attached if any.
+pub fn attach(bpf_fd: &BpfProgFd) -> Result<()> {
+    // The target fd is ignored, the current network namespace is used.
+    libbpf::bpf_prog_attach(bpf_fd, 0, BpfAttachType::FLOW_DISSECTOR, BpfAttachFlags::NONE)
+}
+
+/// Detach `bpf_fd` from the current network namespace. Since Linux 5.7, fails
+/// with ENOENT if another program is attached.
+pub fn detach(bpf_fd: &BpfProgFd) -> Result<()> {
+    libbpf::bpf_prog_detach2(bpf_fd, 0, BpfAttachType::FLOW_DISSECTOR)
+}
+
+/// Returns the id of the program attached to the current network namespace.
+#[named]
+pub fn attached_prog_id() -> Result<Option<u32>> {
+    let netns = File::open("/proc/thread-self/ns/net").map_err(map_io_error(function_name!()))?;
+    let (_, prog_ids) =
+        libbpf::bpf_prog_query(netns.as_raw_fd(), BpfAttachType::FLOW_DISSECTOR, 0)?;
+    Ok(prog_ids.first().cloned())
+}
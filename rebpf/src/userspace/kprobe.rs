@@ -0,0 +1,253 @@
+//! This module contains high-level api to attach `KPROBE` programs to
+//! kernel functions, as kprobes or kretprobes.
+//!
+//! Probes are created through the kprobe PMU when the kernel provides it
+//! (Linux 4.17+), otherwise through the legacy `kprobe_events` tracefs file.
+
+use crate::{
+    error::{Error, Result},
+    libbpf::BpfProgFd,
+    userspace::perf_event::{self, PerfEventAttr, PerfEventLink},
+    utils::*,
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static LEGACY_EVENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A set of kprobes with a bpf program attached, removed when dropped.
+pub struct KprobeLink {
+    perf_link: PerfEventLink,
+    symbols: Vec<String>,
+    legacy_events: Vec<String>,
+}
+
+impl KprobeLink {
+    /// The kernel symbols probed by this link.
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+}
+
+impl Drop for KprobeLink {
+    fn drop(&mut self) {
+        // The legacy events can't be removed while perf events are open on them.
+        self.perf_link.close();
+        for event in &self.legacy_events {
+            let _ = remove_legacy_kprobe(event);
+        }
+    }
+}
+
+/// Attach `bpf_fd` to the kernel function `func_name`, `offset` bytes after its entry.
+pub fn attach_kprobe(bpf_fd: &BpfProgFd, func_name: &str, offset: u64) -> Result<KprobeLink> {
+    let mut link = new_link();
+    attach(&mut link, bpf_fd, func_name, offset, false)?;
+    Ok(link)
+}
+
+/// Attach `bpf_fd` to the return of the kernel function `func_name`.
+pub fn attach_kretprobe(bpf_fd: &BpfProgFd, func_name: &str) -> Result<KprobeLink> {
+    let mut link = new_link();
+    attach(&mut link, bpf_fd, func_name, 0, true)?;
+    Ok(link)
+}
+
+/// Attach `bpf_fd` to the entry (or to the return if `retprobe` is set) of every
+/// kernel function matching the wildcard `pattern`, see `kallsyms_matching`.
+///
+/// Functions that can't be probed (i.e. blacklisted or inlined ones) are skipped,
+/// it is an error if no function at all could be probed.
+pub fn attach_kprobes_matching(
+    bpf_fd: &BpfProgFd,
+    pattern: &str,
+    retprobe: bool,
+) -> Result<KprobeLink> {
+    let mut link = new_link();
+    let mut last_error = None;
+    for func_name in kallsyms_matching(pattern)? {
+        if let Err(e) = attach(&mut link, bpf_fd, &func_name, 0, retprobe) {
+            last_error = Some(e);
+        }
+    }
+    if link.symbols.is_empty() {
+        return Err(last_error.unwrap_or_else(|| {
+            Error::Custom(format!("No kernel function matches {}", pattern))
+        }));
+    }
+    Ok(link)
+}
+
+/// Returns the kernel functions listed in /proc/kallsyms whose name matches
+/// `pattern`, where `*` matches any sequence of characters and `?` any character.
+#[named]
+pub fn kallsyms_matching(pattern: &str) -> Result<Vec<String>> {
+    let kallsyms = fs::read_to_string("/proc/kallsyms").map_err(map_io_error(function_name!()))?;
+    let mut symbols: Vec<String> = kallsyms
+        .lines()
+        .filter_map(parse_kallsyms_line)
+        .filter(|name| glob_match(pattern, name))
+        .map(String::from)
+        .collect();
+    symbols.sort();
+    symbols.dedup();
+    Ok(symbols)
+}
+
+/// Returns the name of the symbol of a /proc/kallsyms line if it is a function.
+fn parse_kallsyms_line(line: &str) -> Option<&str> {
+    let mut fields = line.split_whitespace();
+    let _address = fields.next()?;
+    let symbol_type = fields.next()?;
+    let name = fields.next()?;
+    if symbol_type != "t" && symbol_type != "T" {
+        return None;
+    }
+    Some(name)
+}
+
+pub(crate) fn glob_match(pattern: &str, s: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let s = s.as_bytes();
+    let (mut p, mut i) = (0, 0);
+    // Position of the last `*` in the pattern and of the input matched by it.
+    let mut star: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == s[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((star_p, star_i)) = star {
+            p = star_p + 1;
+            i = star_i + 1;
+            star = Some((star_p, star_i + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+fn new_link() -> KprobeLink {
+    KprobeLink {
+        perf_link: PerfEventLink::new(),
+        symbols: Vec::new(),
+        legacy_events: Vec::new(),
+    }
+}
+
+fn attach(
+    link: &mut KprobeLink,
+    bpf_fd: &BpfProgFd,
+    func_name: &str,
+    offset: u64,
+    retprobe: bool,
+) -> Result<()> {
+    let fd = match perf_event::pmu_type("kprobe") {
+        Some(pmu_type) => {
+            let func_name_cs = str_to_cstring(func_name)?;
+            let mut attr = PerfEventAttr::new(pmu_type, 0);
+            if retprobe {
+                attr.config |= 1 << perf_event::pmu_retprobe_bit("kprobe")?;
+            }
+            attr.config1 = func_name_cs.as_ptr() as u64;
+            attr.config2 = offset;
+            perf_event::perf_event_open(&attr, -1, 0)?
+        }
+        None => {
+            let event = add_legacy_kprobe(func_name, offset, retprobe)?;
+            let id = perf_event::tracepoint_id("kprobes", &event);
+            let fd = id.and_then(|id| {
+                let attr = PerfEventAttr::new(perf_event::PERF_TYPE_TRACEPOINT, id);
+                perf_event::perf_event_open(&attr, -1, 0)
+            });
+            if fd.is_err() {
+                let _ = remove_legacy_kprobe(&event);
+            } else {
+                link.legacy_events.push(event);
+            }
+            fd?
+        }
+    };
+    link.perf_link.push(fd, bpf_fd)?;
+    link.symbols.push(func_name.to_owned());
+    Ok(())
+}
+
+fn write_kprobe_events(function_name: &str, command: &str) -> Result<()> {
+    let path = perf_event::tracefs_path()?.join("kprobe_events");
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map_err(map_io_error(function_name))?;
+    file.write_all(command.as_bytes())
+        .map_err(map_io_error(function_name))
+}
+
+/// Create a legacy kprobe event and returns its name.
+#[named]
+fn add_legacy_kprobe(func_name: &str, offset: u64, retprobe: bool) -> Result<String> {
+    let event = format!(
+        "rebpf_{}_{}_{}_{}",
+        std::process::id(),
+        LEGACY_EVENT_COUNT.fetch_add(1, Ordering::Relaxed),
+        if retprobe { "ret" } else { "entry" },
+        func_name.replace('.', "_"),
+    );
+    let command = format!(
+        "{}:kprobes/{} {}+{}\n",
+        if retprobe { "r" } else { "p" },
+        event,
+        func_name,
+        offset
+    );
+    write_kprobe_events(function_name!(), &command)?;
+    Ok(event)
+}
+
+#[named]
+fn remove_legacy_kprobe(event: &str) -> Result<()> {
+    write_kprobe_events(function_name!(), &format!("-:kprobes/{}\n", event))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn glob_exact() {
+        assert!(glob_match("tcp_v4_connect", "tcp_v4_connect"));
+        assert!(!glob_match("tcp_v4_connect", "tcp_v6_connect"));
+        assert!(!glob_match("tcp", "tcp_v4_connect"));
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob_match("tcp_*", "tcp_v4_connect"));
+        assert!(glob_match("*_connect", "tcp_v4_connect"));
+        assert!(glob_match("tcp_v?_connect", "tcp_v6_connect"));
+        assert!(glob_match("*v*c*", "tcp_v4_connect"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("udp_*", "tcp_v4_connect"));
+        assert!(!glob_match("tcp_v?_connect", "tcp_v44_connect"));
+    }
+
+    #[test]
+    fn kallsyms_functions_only() {
+        assert_eq!(
+            parse_kallsyms_line("ffffffff81000000 T startup_64"),
+            Some("startup_64")
+        );
+        assert_eq!(
+            parse_kallsyms_line("ffffffffc0a01000 t nf_nat_ipv4_fn\t[nf_nat]"),
+            Some("nf_nat_ipv4_fn")
+        );
+        assert_eq!(parse_kallsyms_line("ffffffff82000000 D jiffies"), None);
+        assert_eq!(parse_kallsyms_line(""), None);
+    }
+}
@@ -0,0 +1,405 @@
+//! This module contains a monitor of the network interfaces created and
+//! deleted (RTM_NEWLINK and RTM_DELLINK notifications), and a manager keeping
+//! a declared set of XDP and TC attachments applied as interfaces come and go.
+//!
+//! Example :
+//!
+//! ```no_run
+//! use rebpf::{libbpf, libbpf::XdpFlags, userspace::link_monitor::AttachmentManager};
+//! use std::path::Path;
+//!
+//! # fn main() -> rebpf::error::Result<()> {
+//! let (_bpf_object, bpf_fd) =
+//!     libbpf::bpf_prog_load(Path::new("kern.o"), libbpf::BpfProgType::XDP)?;
+//! let mut manager = AttachmentManager::new()?;
+//! manager.declare_xdp("eth0", bpf_fd, XdpFlags::SKB_MODE)?;
+//! // Attach now, then re-attach whenever eth0 is re-created.
+//! manager.run(|interface, e| eprintln!("{}: {}", interface.name(), e))
+//! # }
+//! ```
+
+use crate::{
+    error::{Error, LibbpfError, Result},
+    interface::{self, Interface},
+    libbpf::{self, BpfProgFd, XdpFlags},
+    netlink::{self, NetlinkSocket},
+    netns::NetNs,
+    userspace::{
+        tc::{self, TcAttachPoint, TcLink},
+        xdp,
+    },
+    utils::*,
+};
+use libc;
+use std::{
+    collections::{HashMap, VecDeque},
+    os::unix::io::{AsRawFd, RawFd},
+    time::Duration,
+};
+
+/// A change of the interfaces of a network namespace.
+#[derive(Debug, Clone)]
+pub enum LinkEvent {
+    /// An interface was created or changed (i.e. set up or down).
+    New(Interface),
+    /// An interface was deleted.
+    Del(Interface),
+}
+
+impl LinkEvent {
+    pub fn interface(&self) -> &Interface {
+        match self {
+            LinkEvent::New(interface) | LinkEvent::Del(interface) => interface,
+        }
+    }
+}
+
+/// A subscription to the link notifications of a network namespace.
+pub struct LinkMonitor {
+    socket: NetlinkSocket,
+    netns: Option<NetNs>,
+    pending: VecDeque<LinkEvent>,
+}
+
+impl LinkMonitor {
+    /// Monitor the interfaces of the namespace of the calling thread.
+    pub fn new() -> Result<LinkMonitor> {
+        LinkMonitor::open(None)
+    }
+
+    /// Monitor the interfaces of the network namespace `netns`.
+    pub fn new_in(netns: &NetNs) -> Result<LinkMonitor> {
+        LinkMonitor::open(Some(netns))
+    }
+
+    fn open(netns: Option<&NetNs>) -> Result<LinkMonitor> {
+        Ok(LinkMonitor {
+            socket: NetlinkSocket::subscribe_in(netns, netlink::RTMGRP_LINK)?,
+            netns: netns.cloned(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Wait for the next event. Fails with ENOBUFS if events have been lost
+    /// because the socket buffer overflowed, the monitor remaining usable.
+    pub fn next_event(&mut self) -> Result<LinkEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            self.receive()?;
+        }
+    }
+
+    /// Wait for the next event at most `timeout`, forever if None.
+    #[named]
+    pub fn poll_event(&mut self, timeout: Option<Duration>) -> Result<Option<LinkEvent>> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(Some(event));
+        }
+        let timeout_ms = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        let mut pollfd = libc::pollfd {
+            fd: self.socket.fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ready < 0 {
+            return map_sys_error(function_name!());
+        }
+        if ready == 0 {
+            return Ok(None);
+        }
+        self.receive()?;
+        Ok(self.pending.pop_front())
+    }
+
+    fn receive(&mut self) -> Result<()> {
+        for (_, reply) in self.socket.recv()? {
+            let netns = self.netns.as_ref();
+            let event = match reply.msg_type {
+                netlink::RTM_NEWLINK => interface::parse_link(&reply, netns).map(LinkEvent::New),
+                netlink::RTM_DELLINK => interface::parse_link(&reply, netns).map(LinkEvent::Del),
+                _ => None,
+            };
+            if let Some(event) = event {
+                self.pending.push_back(event);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for LinkMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.fd()
+    }
+}
+
+enum Attachment {
+    Xdp {
+        prog: BpfProgFd,
+        prog_id: u32,
+        xdp_flags: XdpFlags,
+    },
+    Tc {
+        prog: BpfProgFd,
+        attach_point: TcAttachPoint,
+        handle: u32,
+        priority: u16,
+    },
+}
+
+struct Declaration {
+    interface_name: String,
+    attachment: Attachment,
+}
+
+/// Keeps XDP and TC programs attached to the interfaces they are declared
+/// for, re-attaching them whenever a matching interface is created.
+///
+/// The programs are detached from the existing interfaces when the manager
+/// is dropped.
+pub struct AttachmentManager {
+    monitor: LinkMonitor,
+    declarations: Vec<Declaration>,
+    /// The TC links of the declarations, by declaration index.
+    tc_links: HashMap<usize, TcLink>,
+    /// The interfaces the XDP programs are attached to, by declaration index.
+    xdp_interfaces: HashMap<usize, Interface>,
+}
+
+impl AttachmentManager {
+    /// Manage the interfaces of the namespace of the calling thread.
+    pub fn new() -> Result<AttachmentManager> {
+        Ok(AttachmentManager::with_monitor(LinkMonitor::new()?))
+    }
+
+    /// Manage the interfaces of the network namespace `netns`.
+    pub fn new_in(netns: &NetNs) -> Result<AttachmentManager> {
+        Ok(AttachmentManager::with_monitor(LinkMonitor::new_in(netns)?))
+    }
+
+    fn with_monitor(monitor: LinkMonitor) -> AttachmentManager {
+        AttachmentManager {
+            monitor,
+            declarations: Vec::new(),
+            tc_links: HashMap::new(),
+            xdp_interfaces: HashMap::new(),
+        }
+    }
+
+    /// Declare that the XDP program `prog` must be attached to the interface
+    /// `interface_name` in the mode selected by `xdp_flags`.
+    ///
+    /// A program attached by someone else is never replaced: the attachment
+    /// fails with `Error::UnexpectedXdpProg` instead.
+    pub fn declare_xdp(
+        &mut self,
+        interface_name: &str,
+        prog: BpfProgFd,
+        xdp_flags: XdpFlags,
+    ) -> Result<()> {
+        let prog_id = libbpf::bpf_obj_get_info_by_fd(&prog)?.id();
+        self.declarations.push(Declaration {
+            interface_name: interface_name.to_owned(),
+            attachment: Attachment::Xdp {
+                prog,
+                prog_id,
+                xdp_flags,
+            },
+        });
+        Ok(())
+    }
+
+    /// Declare that the `SCHED_CLS` program `prog` must be attached to the
+    /// `attach_point` of the interface `interface_name`, see `tc::attach`.
+    pub fn declare_tc(
+        &mut self,
+        interface_name: &str,
+        prog: BpfProgFd,
+        attach_point: TcAttachPoint,
+        handle: u32,
+        priority: u16,
+    ) {
+        self.declarations.push(Declaration {
+            interface_name: interface_name.to_owned(),
+            attachment: Attachment::Tc {
+                prog,
+                attach_point,
+                handle,
+                priority,
+            },
+        });
+    }
+
+    /// Apply the declarations to the existing interfaces, and forget the
+    /// attachments of the interfaces that don't exist anymore. Returns the
+    /// errors of the attachments that failed, with their interface.
+    pub fn apply(&mut self) -> Result<Vec<(Interface, Error)>> {
+        let interfaces = match &self.monitor.netns {
+            Some(netns) => interface::interfaces_in(netns)?,
+            None => interface::interfaces()?,
+        };
+        let mut stale: Vec<u32> = self
+            .tc_links
+            .values()
+            .map(|link| link.interface().ifindex())
+            .chain(self.xdp_interfaces.values().map(Interface::ifindex))
+            .filter(|&ifindex| interfaces.iter().all(|i| i.ifindex() != ifindex))
+            .collect();
+        stale.sort_unstable();
+        stale.dedup();
+        for ifindex in stale {
+            self.forget(ifindex);
+        }
+        let mut errors = Vec::new();
+        for interface in interfaces {
+            if let Err(e) = self.apply_to(&interface) {
+                errors.push((interface, e));
+            }
+        }
+        Ok(errors)
+    }
+
+    /// Update the attachments according to `event`.
+    pub fn handle_event(&mut self, event: &LinkEvent) -> Result<()> {
+        match event {
+            LinkEvent::New(interface) => self.apply_to(interface),
+            LinkEvent::Del(interface) => {
+                self.forget(interface.ifindex());
+                Ok(())
+            }
+        }
+    }
+
+    /// Apply the declarations to the existing interfaces, then keep them
+    /// applied until an error occurs while receiving events. The attachment
+    /// errors are reported to `on_error`.
+    ///
+    /// If events are lost because the socket buffer overflowed (ENOBUFS), the
+    /// declarations are applied again to the existing interfaces.
+    pub fn run(&mut self, mut on_error: impl FnMut(&Interface, Error)) -> Result<()> {
+        for (interface, e) in self.apply()? {
+            on_error(&interface, e);
+        }
+        loop {
+            let event = match self.monitor.next_event() {
+                Ok(event) => event,
+                Err(Error::Sys(_, ref e)) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                    for (interface, e) in self.apply()? {
+                        on_error(&interface, e);
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Err(e) = self.handle_event(&event) {
+                on_error(event.interface(), e);
+            }
+        }
+    }
+
+    /// Attach the programs declared for `interface` that aren't attached yet.
+    fn apply_to(&mut self, interface: &Interface) -> Result<()> {
+        let mut result = Ok(());
+        for (index, declaration) in self.declarations.iter().enumerate() {
+            if declaration.interface_name != interface.name() {
+                continue;
+            }
+            let r = match &declaration.attachment {
+                Attachment::Xdp {
+                    prog,
+                    prog_id,
+                    xdp_flags,
+                } => match xdp::query(interface) {
+                    Ok(link_info) if link_info.prog_id_by_mode(*xdp_flags) == Some(*prog_id) => {
+                        Ok(())
+                    }
+                    Ok(link_info) if link_info.prog_id_by_mode(*xdp_flags).is_some() => Err(
+                        Error::UnexpectedXdpProg(*prog_id, link_info.prog_id_by_mode(*xdp_flags)),
+                    ),
+                    Ok(_) => {
+                        let flags = *xdp_flags | XdpFlags::UPDATE_IF_NOEXIST;
+                        match libbpf::bpf_set_link_xdp_fd(interface, Some(prog), flags) {
+                            Ok(()) => {
+                                self.xdp_interfaces.insert(index, interface.clone());
+                                Ok(())
+                            }
+                            // Someone else attached a program in the meantime.
+                            Err(Error::Libbpf(_, LibbpfError::LibbpfSys(e)))
+                                if e == -libc::EEXIST =>
+                            {
+                                xdp::query(interface).and_then(|link_info| {
+                                    let attached_id = link_info.prog_id_by_mode(*xdp_flags);
+                                    Err(Error::UnexpectedXdpProg(*prog_id, attached_id))
+                                })
+                            }
+                            Err(e) => Err(e),
+                        }
+                    }
+                    Err(e) => Err(e),
+                },
+                Attachment::Tc {
+                    prog,
+                    attach_point,
+                    handle,
+                    priority,
+                } => {
+                    let attached = self
+                        .tc_links
+                        .get(&index)
+                        .is_some_and(|link| link.interface().ifindex() == interface.ifindex());
+                    if attached {
+                        Ok(())
+                    } else {
+                        match tc::attach(interface, prog, *attach_point, *handle, *priority) {
+                            Ok(link) => {
+                                if let Some(stale) = self.tc_links.insert(index, link) {
+                                    // The filter of a deleted interface is already gone.
+                                    stale.disarm();
+                                }
+                                Ok(())
+                            }
+                            Err(e) => Err(e),
+                        }
+                    }
+                }
+            };
+            if result.is_ok() {
+                result = r;
+            }
+        }
+        result
+    }
+
+    /// Forget the attachments of the deleted interface `ifindex`.
+    fn forget(&mut self, ifindex: u32) {
+        let stale: Vec<usize> = self
+            .tc_links
+            .iter()
+            .filter(|(_, link)| link.interface().ifindex() == ifindex)
+            .map(|(&index, _)| index)
+            .collect();
+        for index in stale {
+            if let Some(link) = self.tc_links.remove(&index) {
+                link.disarm();
+            }
+        }
+        self.xdp_interfaces.retain(|_, i| i.ifindex() != ifindex);
+    }
+}
+
+impl Drop for AttachmentManager {
+    fn drop(&mut self) {
+        // The TC links detach themselves when dropped.
+        for (index, interface) in &self.xdp_interfaces {
+            if let Attachment::Xdp {
+                prog_id, xdp_flags, ..
+            } = &self.declarations[*index].attachment
+            {
+                let _ = xdp::detach_prog(interface, *prog_id, *xdp_flags);
+            }
+        }
+    }
+}
@@ -3,10 +3,17 @@
 
 use crate::error::{Error, Result};
 use crate::libbpf;
-use crate::libbpf::{BpfMapDef, BpfMapFd, BpfMapInfo, BpfMapType, BpfObject, BpfUpdateElemFlags};
+use crate::lpm::{LpmData, LpmKey, LPM_TRIE_MAP_FLAGS};
+use crate::libbpf::{
+    BpfAttachFlags, BpfAttachType, BpfFd, BpfMapDef, BpfMapFd, BpfMapInfo, BpfMapType, BpfObject,
+    BpfProgFd, BpfUpdateElemFlags,
+};
 use crate::map_layout::*;
 use duplicate::duplicate_inline;
+use lazy_static::lazy_static;
+use libc;
 use maybe_uninit::MaybeUninit;
+use std::os::unix::io::AsRawFd;
 
 /// This trait is implemented by all the map wrapper types, as
 /// as convenient way to communicate their underlying types to the
@@ -19,17 +26,20 @@ pub trait Map {
     fn fd(&self) -> &BpfMapFd<Self::Key, Self::Value, Self::Layout>;
 }
 
+/// A copy of a value of the map `M`, as returned by the lookups.
+pub type ValueBuffer<M> = <<M as Map>::Layout as MapLayout<<M as Map>::Value>>::Buffer;
+
 pub trait Update: Map {
     /// Update a value inside the map.
     ///
     /// This operation is considered as atomic.
-    fn update<'a>(
-        &'a mut self,
+    fn update(
+        &mut self,
         key: &Self::Key,
         value: &<<Self as Map>::Layout as MapLayout<Self::Value>>::Buffer,
         flags: BpfUpdateElemFlags,
     ) -> Result<()> {
-        libbpf::bpf_map_update_elem(&self.fd(), key, value, flags)
+        libbpf::bpf_map_update_elem(self.fd(), key, value, flags)
     }
 }
 pub trait Lookup: Map {
@@ -41,11 +51,383 @@ pub trait Lookup: Map {
         key: &Self::Key,
     ) -> Option<<<Self as Map>::Layout as MapLayout<Self::Value>>::Buffer> {
         let mut buffer = Self::Layout::allocate_write();
-        libbpf::bpf_map_lookup_elem(&self.fd(), key, &mut buffer)
+        libbpf::bpf_map_lookup_elem(self.fd(), key, &mut buffer)
             .map(|_| unsafe { Self::Layout::transmute(buffer) })
     }
 }
 
+pub trait Delete: Map {
+    /// Delete the entry associated with the given key.
+    ///
+    /// Returns `Error::KeyNotFound` if the map has no such entry.
+    fn delete(&mut self, key: &Self::Key) -> Result<()> {
+        libbpf::bpf_map_delete_elem(self.fd(), key)
+    }
+}
+
+pub trait LookupAndDelete: Map {
+    /// Atomically lookup and delete the entry associated with the given key,
+    /// returning a copy of its content.
+    ///
+    /// Returns `Error::KeyNotFound` if the map has no such entry. Hash maps
+    /// support this operation since Linux 5.14.
+    fn lookup_and_delete(
+        &mut self,
+        key: &Self::Key,
+    ) -> Result<<<Self as Map>::Layout as MapLayout<Self::Value>>::Buffer> {
+        let mut buffer = Self::Layout::allocate_write();
+        libbpf::bpf_map_lookup_and_delete_elem(self.fd(), key, &mut buffer)?;
+        Ok(unsafe { Self::Layout::transmute(buffer) })
+    }
+}
+
+pub trait Iterate: Map {
+    /// Iterate over the keys of the map, following `bpf_map_get_next_key`.
+    ///
+    /// The iteration stops after yielding the first error reported by the kernel.
+    ///
+    /// Entries may be inserted or deleted while iterating. Deleting any entry
+    /// but the last returned key is harmless. If the last returned key is
+    /// deleted, a hash map restarts the iteration from its first key, so
+    /// some keys may be returned twice. Arrays always keep all their keys.
+    fn keys(&self) -> MapKeys<'_, Self>
+    where
+        Self: Sized,
+    {
+        MapKeys {
+            map: self,
+            key: None,
+            done: false,
+        }
+    }
+
+    /// Iterate over the entries of the map, as keys with a copy of their
+    /// content. Entries deleted between the retrieval of their key and of
+    /// their content are skipped, see `keys` for the other caveats.
+    ///
+    /// ```no_run
+    /// # use rebpf::{libbpf, userspace::maps::{HashMap, Iterate}};
+    /// # fn main() -> rebpf::error::Result<()> {
+    /// # let bpf_object = libbpf::bpf_object__open(std::path::Path::new("kern.o"))?;
+    /// let flows = HashMap::<u32, u64>::from_obj(&bpf_object, "flows")?;
+    /// for entry in flows.iter() {
+    ///     let (addr, packets) = entry?;
+    ///     println!("{}: {}", addr, packets);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn iter(&self) -> MapIter<'_, Self>
+    where
+        Self: Sized + Lookup,
+    {
+        MapIter { keys: self.keys() }
+    }
+}
+
+/// An iterator over the keys of a map, see `Iterate::keys`.
+pub struct MapKeys<'a, M: Map> {
+    map: &'a M,
+    key: Option<M::Key>,
+    done: bool,
+}
+
+impl<'a, M: Map> Iterator for MapKeys<'a, M>
+where
+    M::Key: Clone,
+{
+    type Item = Result<M::Key>;
+
+    fn next(&mut self) -> Option<Result<M::Key>> {
+        if self.done {
+            return None;
+        }
+        match libbpf::bpf_map_get_next_key(self.map.fd(), self.key.as_ref()) {
+            Ok(Some(key)) => {
+                self.key = Some(key.clone());
+                Some(Ok(key))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// An iterator over the entries of a map, see `Iterate::iter`.
+pub struct MapIter<'a, M: Map> {
+    keys: MapKeys<'a, M>,
+}
+
+impl<'a, M: Lookup> Iterator for MapIter<'a, M>
+where
+    M::Key: Clone,
+{
+    type Item = Result<(
+        M::Key,
+        <<M as Map>::Layout as MapLayout<<M as Map>::Value>>::Buffer,
+    )>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let key = match self.keys.next()? {
+                Ok(key) => key,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some(value) = self.keys.map.lookup(&key) {
+                return Some(Ok((key, value)));
+            }
+        }
+    }
+}
+
+/// The number of entries read per syscall by the batched lookups.
+const LOOKUP_BATCH_SIZE: usize = 1024;
+
+/// The kernel internal ENOTSUPP, reported for maps without batch support.
+const ENOTSUPP: i32 = 524;
+
+pub trait LookupBatch: Iterate + Lookup
+where
+    Self::Layout: BatchLayout<Self::Value>,
+{
+    /// Lookup the next chunk of entries through BPF_MAP_LOOKUP_BATCH (Linux
+    /// 5.6+), filling `keys` and `values` which must have the same length.
+    /// `in_batch` is None for the first chunk, then the token returned by the
+    /// previous call.
+    ///
+    /// Returns the number of entries read, and the token of the next chunk or
+    /// None once the end of the map is reached. Fails with ENOSPC if a hash
+    /// bucket holds more entries than `keys`.
+    fn lookup_batch_chunk(
+        &self,
+        in_batch: Option<u64>,
+        keys: &mut [Self::Key],
+        values: &mut BatchBuffer<Self::Value, Self::Layout>,
+    ) -> Result<(usize, Option<u64>)> {
+        let keys = unsafe { uninit_keys(keys) };
+        lookup_chunk(
+            self.fd(),
+            libbpf::bpf_map_lookup_batch,
+            in_batch,
+            keys,
+            values,
+        )
+    }
+
+    /// Lookup every entry of the map, returning copies of their content.
+    ///
+    /// The entries are read by chunks with `lookup_batch_chunk`, or one at a
+    /// time with `iter` on kernels without batch support.
+    fn lookup_batch(&self) -> Result<Vec<(Self::Key, ValueBuffer<Self>)>>
+    where
+        Self: Sized,
+        Self::Key: Clone,
+    {
+        let mut entries = Vec::new();
+        match lookup_entries(self.fd(), libbpf::bpf_map_lookup_batch, &mut entries) {
+            Err(ref e) if batch_unsupported(e) => self.iter().collect(),
+            r => r.map(|()| entries),
+        }
+    }
+}
+
+pub trait LookupAndDeleteBatch: Iterate + Lookup + Delete
+where
+    Self::Layout: BatchLayout<Self::Value>,
+{
+    /// Lookup and delete the next chunk of entries through
+    /// BPF_MAP_LOOKUP_AND_DELETE_BATCH (Linux 5.6+), see `lookup_batch_chunk`.
+    fn lookup_and_delete_batch_chunk(
+        &mut self,
+        in_batch: Option<u64>,
+        keys: &mut [Self::Key],
+        values: &mut BatchBuffer<Self::Value, Self::Layout>,
+    ) -> Result<(usize, Option<u64>)> {
+        let keys = unsafe { uninit_keys(keys) };
+        lookup_chunk(
+            self.fd(),
+            libbpf::bpf_map_lookup_and_delete_batch,
+            in_batch,
+            keys,
+            values,
+        )
+    }
+
+    /// Lookup and delete every entry of the map, appending copies of their
+    /// content to `entries`. On error, `entries` holds the entries deleted
+    /// before the failure.
+    ///
+    /// The entries are read and deleted by chunks with
+    /// `lookup_and_delete_batch_chunk`. On kernels without batch support, each
+    /// entry is looked up then deleted: an update happening in between is lost.
+    fn lookup_and_delete_batch(
+        &mut self,
+        entries: &mut Vec<(Self::Key, ValueBuffer<Self>)>,
+    ) -> Result<()>
+    where
+        Self: Sized,
+        Self::Key: Clone,
+    {
+        match lookup_entries(self.fd(), libbpf::bpf_map_lookup_and_delete_batch, entries) {
+            Err(ref e) if batch_unsupported(e) => {}
+            r => return r,
+        }
+        // Deleting the entries while iterating would restart the iteration.
+        let keys = self.keys().collect::<Result<Vec<_>>>()?;
+        for key in keys {
+            if let Some(value) = self.lookup(&key) {
+                match self.delete(&key) {
+                    Ok(()) => entries.push((key, value)),
+                    Err(Error::KeyNotFound) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub trait UpdateBatch: Update
+where
+    Self::Layout: BatchLayout<Self::Value>,
+{
+    /// Update the entries of `keys` with the corresponding `values`.
+    ///
+    /// The entries are updated at once through BPF_MAP_UPDATE_BATCH (Linux
+    /// 5.6+), or one at a time on kernels without batch support. On error,
+    /// the entries before the failing one are updated.
+    fn update_batch(
+        &mut self,
+        keys: &[Self::Key],
+        values: &[<<Self as Map>::Layout as MapLayout<Self::Value>>::Buffer],
+        flags: BpfUpdateElemFlags,
+    ) -> Result<()> {
+        if keys.len() != values.len() {
+            return Err(Error::Custom(
+                "The numbers of keys and values differ".to_owned(),
+            ));
+        }
+        let buffer = BatchBuffer::from_values(values);
+        match libbpf::bpf_map_update_batch(self.fd(), keys, &buffer, flags) {
+            Err(ref e) if batch_unsupported(e) => {}
+            r => return r,
+        }
+        for (key, value) in keys.iter().zip(values) {
+            self.update(key, value, flags)?;
+        }
+        Ok(())
+    }
+}
+
+pub trait DeleteBatch: Delete {
+    /// Delete the entries of `keys`.
+    ///
+    /// The entries are deleted at once through BPF_MAP_DELETE_BATCH (Linux
+    /// 5.6+), or one at a time on kernels without batch support. Returns
+    /// `Error::KeyNotFound` if the map has no entry for one of `keys`, the
+    /// entries of the keys before it being deleted.
+    fn delete_batch(&mut self, keys: &[Self::Key]) -> Result<()> {
+        match libbpf::bpf_map_delete_batch(self.fd(), keys) {
+            Err(ref e) if batch_unsupported(e) => {}
+            r => return r,
+        }
+        for key in keys {
+            self.delete(key)?;
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref BATCH_SUPPORTED: bool = libbpf::bpf_map_batch_supported();
+}
+
+/// Returns true if `e` reports that the kernel or the map doesn't support
+/// the batched operations.
+fn batch_unsupported(e: &Error) -> bool {
+    match e {
+        Error::Sys(_, e) => match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(ENOTSUPP) => true,
+            // The unknown commands of older kernels, not an invalid argument.
+            Some(libc::EINVAL) => !*BATCH_SUPPORTED,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+type LookupBatchFn<K, V, L> = fn(
+    &BpfMapFd<K, V, L>,
+    Option<&u64>,
+    &mut u64,
+    &mut [MaybeUninit<K>],
+    &mut BatchBuffer<V, L>,
+) -> Result<(usize, bool)>;
+
+/// View initialized keys as a buffer for the batched lookups to write into.
+///
+/// # Safety
+///
+/// The keys written by the kernel must be valid values of `K`, as for
+/// `bpf_map_get_next_key`.
+unsafe fn uninit_keys<K>(keys: &mut [K]) -> &mut [MaybeUninit<K>] {
+    std::slice::from_raw_parts_mut(keys.as_mut_ptr() as *mut MaybeUninit<K>, keys.len())
+}
+
+/// Read a chunk of entries with `lookup`, a batched lookup, returning the
+/// number of entries read and the token of the next chunk if any.
+fn lookup_chunk<K, V, L: BatchLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    lookup: LookupBatchFn<K, V, L>,
+    in_batch: Option<u64>,
+    keys: &mut [MaybeUninit<K>],
+    values: &mut BatchBuffer<V, L>,
+) -> Result<(usize, Option<u64>)> {
+    let mut out_batch = 0;
+    let (count, done) = lookup(map_fd, in_batch.as_ref(), &mut out_batch, keys, values)?;
+    Ok((count, if done { None } else { Some(out_batch) }))
+}
+
+/// Read every entry of the map by chunks with `lookup`, a batched lookup,
+/// appending them to `entries`. On error, `entries` holds the entries read
+/// before the failure.
+fn lookup_entries<K, V, L: BatchLayout<V>>(
+    map_fd: &BpfMapFd<K, V, L>,
+    lookup: LookupBatchFn<K, V, L>,
+    entries: &mut Vec<(K, L::Buffer)>,
+) -> Result<()> {
+    let mut batch_size = LOOKUP_BATCH_SIZE;
+    let mut in_batch = None;
+    loop {
+        let mut keys: Vec<MaybeUninit<K>> = std::iter::repeat_with(MaybeUninit::uninit)
+            .take(batch_size)
+            .collect();
+        let mut values = BatchBuffer::with_len(batch_size);
+        let chunk = lookup_chunk(map_fd, lookup, in_batch, &mut keys, &mut values);
+        let (count, out_batch) = match chunk {
+            // A hash bucket holds more entries than the chunk.
+            Err(Error::Sys(_, ref e)) if e.raw_os_error() == Some(libc::ENOSPC) => {
+                batch_size *= 2;
+                continue;
+            }
+            r => r?,
+        };
+        for (i, key) in keys.into_iter().take(count).enumerate() {
+            entries.push(unsafe { (key.assume_init(), values.read(i)) });
+        }
+        match out_batch {
+            Some(out_batch) => in_batch = Some(out_batch),
+            None => return Ok(()),
+        }
+    }
+}
+
 fn extract_map_fd<K, V, L: MapLayout<V>>(
     bpf_obj: &BpfObject,
     map_name: &str,
@@ -54,12 +436,17 @@ fn extract_map_fd<K, V, L: MapLayout<V>>(
     libbpf::bpf_map__fd(&bpf_map)
 }
 
+/// Check that the map has the type and the sizes of the wrapper, and at least
+/// the `BPF_F_*` flags `map_flags`.
 fn extract_checked_info<K, V, L: MapLayout<V>>(
     map_fd: &BpfMapFd<K, V, L>,
     map_type: BpfMapType,
+    map_flags: u32,
 ) -> Result<BpfMapInfo> {
     let info = libbpf::bpf_obj_get_info_by_fd(map_fd)?;
-    if info.matches_map_def::<K, V>(&BpfMapDef::new(map_type, 0)) {
+    if info.matches_map_def::<K, V>(&BpfMapDef::new(map_type, 0))
+        && info.map_flags() & map_flags == map_flags
+    {
         Ok(info)
     } else {
         Err(Error::Custom(
@@ -70,10 +457,19 @@ fn extract_checked_info<K, V, L: MapLayout<V>>(
 
 duplicate_inline!{
 [
-  map_type            generics  key      value    layout            type_const;     
-  [ CpuMap ]          [ ]       [ u32 ]  [ u32 ]  [ ScalarLayout ]  [ BpfMapType::CPUMAP ];
-  [ Array ]           [ T ]     [ u32 ]  [ T ]    [ ScalarLayout ]  [ BpfMapType::ARRAY ];
-  [ PerCpuArray ]     [ T ]     [ u32 ]  [ T ]    [ PerCpuLayout ]  [ BpfMapType::PERCPU_ARRAY ];
+  map_type                generics  key            value    layout            type_const                          map_flags;
+  [ CpuMap ]              [ ]       [ u32 ]        [ u32 ]  [ ScalarLayout ]  [ BpfMapType::CPUMAP ]               [ 0 ];
+  [ ProgArray ]           [ ]       [ u32 ]        [ u32 ]  [ ScalarLayout ]  [ BpfMapType::PROG_ARRAY ]           [ 0 ];
+  [ Array ]               [ T ]     [ u32 ]        [ T ]    [ ScalarLayout ]  [ BpfMapType::ARRAY ]                [ 0 ];
+  [ PerCpuArray ]         [ T ]     [ u32 ]        [ T ]    [ PerCpuLayout ]  [ BpfMapType::PERCPU_ARRAY ]         [ 0 ];
+  [ SockMap ]             [ ]       [ u32 ]        [ u32 ]  [ ScalarLayout ]  [ BpfMapType::SOCKMAP ]              [ 0 ];
+  [ SockHash ]            [ K ]     [ K ]          [ u32 ]  [ ScalarLayout ]  [ BpfMapType::SOCKHASH ]             [ 0 ];
+  [ ReuseportSockArray ]  [ ]       [ u32 ]        [ u32 ]  [ ScalarLayout ]  [ BpfMapType::REUSEPORT_SOCKARRAY ]  [ 0 ];
+  [ HashMap ]             [ K, V ]  [ K ]          [ V ]    [ ScalarLayout ]  [ BpfMapType::HASH ]                 [ 0 ];
+  [ LruHashMap ]          [ K, V ]  [ K ]          [ V ]    [ ScalarLayout ]  [ BpfMapType::LRU_HASH ]             [ 0 ];
+  [ PerCpuHashMap ]       [ K, V ]  [ K ]          [ V ]    [ PerCpuLayout ]  [ BpfMapType::PERCPU_HASH ]          [ 0 ];
+  [ LruPerCpuHashMap ]    [ K, V ]  [ K ]          [ V ]    [ PerCpuLayout ]  [ BpfMapType::LRU_PERCPU_HASH ]      [ 0 ];
+  [ LpmTrie ]             [ T, V ]  [ LpmKey<T> ]  [ V ]    [ ScalarLayout ]  [ BpfMapType::LPM_TRIE ]             [ LPM_TRIE_MAP_FLAGS ];
 ]
     pub struct map_type<generics> {
         fd: BpfMapFd<key, value, layout>,
@@ -90,9 +486,260 @@ duplicate_inline!{
             Ok(Self { fd })
         }
         pub fn extract_info(&self) -> Result<BpfMapInfo> {
-            extract_checked_info(&self.fd, type_const)
+            extract_checked_info(&self.fd, type_const, map_flags)
         }
     }
+}
+
+// The socket maps only provide typed insert and remove methods: their values
+// are sockets fds when updating but socket cookies when looking up.
+duplicate_inline!{
+[
+  map_type                generics;
+  [ CpuMap ]              [ ];
+  [ ProgArray ]           [ ];
+  [ Array ]               [ T ];
+  [ PerCpuArray ]         [ T ];
+  [ HashMap ]             [ K, V ];
+  [ LruHashMap ]          [ K, V ];
+  [ PerCpuHashMap ]       [ K, V ];
+  [ LruPerCpuHashMap ]    [ K, V ];
+  [ LpmTrie ]             [ T, V ];
+]
     impl<generics> Update for map_type<generics> {}
     impl<generics> Lookup for map_type<generics> {}
 }
+
+duplicate_inline!{
+[
+  map_type      generics;
+  [ SockMap ]   [ ];
+  [ SockHash ]  [ K ];
+]
+    impl<generics> map_type<generics> {
+        /// Insert the socket `socket`, i.e. a `TcpStream`, at `key`.
+        pub fn insert<S: AsRawFd>(&mut self, key: &<Self as Map>::Key, socket: &S) -> Result<()> {
+            let fd = socket.as_raw_fd() as u32;
+            libbpf::bpf_map_update_elem(&self.fd, key, &fd, BpfUpdateElemFlags::ANY)
+        }
+
+        /// Remove the socket at `key`.
+        pub fn remove(&mut self, key: &<Self as Map>::Key) -> Result<()> {
+            libbpf::bpf_map_delete_elem(&self.fd, key)
+        }
+
+        /// Attach the `SK_SKB` program `bpf_fd` as stream parser of the sockets
+        /// of the map. It returns the length of the next message of the stream.
+        pub fn attach_stream_parser(&self, bpf_fd: &BpfProgFd) -> Result<()> {
+            self.attach(bpf_fd, BpfAttachType::SK_SKB_STREAM_PARSER)
+        }
+
+        /// Attach the `SK_SKB` program `bpf_fd` as stream verdict of the sockets
+        /// of the map. It decides whether to pass, drop or redirect each message.
+        pub fn attach_stream_verdict(&self, bpf_fd: &BpfProgFd) -> Result<()> {
+            self.attach(bpf_fd, BpfAttachType::SK_SKB_STREAM_VERDICT)
+        }
+
+        /// Attach the `SK_MSG` program `bpf_fd` as verdict of the messages sent
+        /// on the sockets of the map.
+        pub fn attach_msg_verdict(&self, bpf_fd: &BpfProgFd) -> Result<()> {
+            self.attach(bpf_fd, BpfAttachType::SK_MSG_VERDICT)
+        }
+
+        /// Detach `bpf_fd` from the `attach_type` hook of the map.
+        pub fn detach(&self, bpf_fd: &BpfProgFd, attach_type: BpfAttachType) -> Result<()> {
+            libbpf::bpf_prog_detach2(bpf_fd, self.fd.fd(), attach_type)
+        }
+
+        fn attach(&self, bpf_fd: &BpfProgFd, attach_type: BpfAttachType) -> Result<()> {
+            libbpf::bpf_prog_attach(bpf_fd, self.fd.fd(), attach_type, BpfAttachFlags::NONE)
+        }
+    }
+}
+
+impl ReuseportSockArray {
+    /// Insert the socket `socket` at `key`. The socket must be bound with
+    /// `SO_REUSEPORT`, and belong to the same reuseport group as the other
+    /// sockets of the map.
+    pub fn insert<S: AsRawFd>(&mut self, key: u32, socket: &S) -> Result<()> {
+        let fd = socket.as_raw_fd() as u32;
+        libbpf::bpf_map_update_elem(&self.fd, &key, &fd, BpfUpdateElemFlags::ANY)
+    }
+
+    /// Remove the socket at `key`.
+    pub fn remove(&mut self, key: u32) -> Result<()> {
+        libbpf::bpf_map_delete_elem(&self.fd, &key)
+    }
+}
+
+duplicate_inline!{
+[
+  map_type              generics;
+  [ HashMap ]           [ K, V ];
+  [ LruHashMap ]        [ K, V ];
+  [ PerCpuHashMap ]     [ K, V ];
+  [ LruPerCpuHashMap ]  [ K, V ];
+]
+    impl<generics> LookupAndDelete for map_type<generics> {}
+    impl<generics> LookupAndDeleteBatch for map_type<generics> {}
+    impl<generics> DeleteBatch for map_type<generics> {}
+}
+
+duplicate_inline!{
+[
+  map_type              generics;
+  [ Array ]             [ T ];
+  [ PerCpuArray ]       [ T ];
+  [ HashMap ]           [ K, V ];
+  [ LruHashMap ]        [ K, V ];
+  [ PerCpuHashMap ]     [ K, V ];
+  [ LruPerCpuHashMap ]  [ K, V ];
+]
+    impl<generics> Iterate for map_type<generics> {}
+    impl<generics> LookupBatch for map_type<generics> {}
+    impl<generics> UpdateBatch for map_type<generics> {}
+}
+
+duplicate_inline!{
+[
+  map_type                generics;
+  [ CpuMap ]              [ ];
+  [ ProgArray ]           [ ];
+  [ SockMap ]             [ ];
+  [ SockHash ]            [ K ];
+  [ HashMap ]             [ K, V ];
+  [ LruHashMap ]          [ K, V ];
+  [ PerCpuHashMap ]       [ K, V ];
+  [ LruPerCpuHashMap ]    [ K, V ];
+  [ LpmTrie ]             [ T, V ];
+]
+    impl<generics> Delete for map_type<generics> {}
+}
+
+impl<T, V> Iterate for LpmTrie<T, V> {}
+
+impl<T: LpmData, V> LpmTrie<T, V> {
+    /// Insert `value` for the CIDR `cidr`, i.e. `10.0.0.0/8` or `2001:db8::/32`,
+    /// replacing the value of the same prefix if any.
+    pub fn insert(&mut self, cidr: &str, value: &V) -> Result<()> {
+        let key: LpmKey<T> = cidr.parse()?;
+        libbpf::bpf_map_update_elem(&self.fd, &key, value, BpfUpdateElemFlags::ANY)
+    }
+
+    /// Remove the CIDR `cidr`. Returns `Error::KeyNotFound` if the trie
+    /// doesn't hold this exact prefix.
+    pub fn remove(&mut self, cidr: &str) -> Result<()> {
+        let key: LpmKey<T> = cidr.parse()?;
+        libbpf::bpf_map_delete_elem(&self.fd, &key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ENTRIES: u64 = 3000;
+    /// The batch size needed to read the fake map, as if a hash bucket held
+    /// more entries than the default batch size.
+    const MIN_BATCH_SIZE: usize = 2048;
+
+    /// A batched lookup over a fake map holding the keys 0..ENTRIES, each
+    /// with twice its key as value. The batch cursor is the next key.
+    fn fake_lookup(
+        _map_fd: &BpfMapFd<u64, u64, ScalarLayout>,
+        in_batch: Option<&u64>,
+        out_batch: &mut u64,
+        keys: &mut [MaybeUninit<u64>],
+        values: &mut BatchBuffer<u64, ScalarLayout>,
+    ) -> Result<(usize, bool)> {
+        if keys.len() < MIN_BATCH_SIZE {
+            return Err(Error::Sys(
+                "fake_lookup".to_owned(),
+                std::io::Error::from_raw_os_error(libc::ENOSPC),
+            ));
+        }
+        let start = in_batch.cloned().unwrap_or(0);
+        let end = (start + keys.len() as u64).min(ENTRIES);
+        let words = values.as_mut_ptr() as *mut u64;
+        for (i, key) in (start..end).enumerate() {
+            keys[i] = MaybeUninit::new(key);
+            unsafe { *words.add(i) = key * 2 };
+        }
+        *out_batch = end;
+        Ok(((end - start) as usize, end == ENTRIES))
+    }
+
+    fn unsupported_lookup(
+        _map_fd: &BpfMapFd<u64, u64, ScalarLayout>,
+        _in_batch: Option<&u64>,
+        _out_batch: &mut u64,
+        _keys: &mut [MaybeUninit<u64>],
+        _values: &mut BatchBuffer<u64, ScalarLayout>,
+    ) -> Result<(usize, bool)> {
+        Err(Error::Sys(
+            "unsupported_lookup".to_owned(),
+            std::io::Error::from_raw_os_error(ENOTSUPP),
+        ))
+    }
+
+    /// Fails after the first chunk, like a lookup interrupted by a signal.
+    fn failing_lookup(
+        map_fd: &BpfMapFd<u64, u64, ScalarLayout>,
+        in_batch: Option<&u64>,
+        out_batch: &mut u64,
+        keys: &mut [MaybeUninit<u64>],
+        values: &mut BatchBuffer<u64, ScalarLayout>,
+    ) -> Result<(usize, bool)> {
+        if in_batch.is_some() {
+            return Err(Error::Sys(
+                "failing_lookup".to_owned(),
+                std::io::Error::from_raw_os_error(libc::EINTR),
+            ));
+        }
+        fake_lookup(map_fd, in_batch, out_batch, keys, values)
+    }
+
+    #[test]
+    fn lookup_entries_grows_batch() {
+        let map_fd = BpfMapFd::new(-1);
+        let mut entries = Vec::new();
+        lookup_entries(&map_fd, fake_lookup, &mut entries).unwrap();
+        assert_eq!(entries.len(), ENTRIES as usize);
+        assert!(entries
+            .iter()
+            .enumerate()
+            .all(|(i, &(key, value))| key == i as u64 && value == key * 2));
+    }
+
+    #[test]
+    fn lookup_entries_reports_unsupported() {
+        let map_fd = BpfMapFd::new(-1);
+        match lookup_entries(&map_fd, unsupported_lookup, &mut Vec::new()) {
+            Err(ref e) => assert!(batch_unsupported(e)),
+            Ok(()) => panic!("the lookup must fail"),
+        }
+        let sys_error = |errno| Error::Sys(String::new(), std::io::Error::from_raw_os_error(errno));
+        assert!(batch_unsupported(&sys_error(libc::EOPNOTSUPP)));
+        assert!(!batch_unsupported(&sys_error(libc::ENOENT)));
+        assert!(!batch_unsupported(&Error::KeyNotFound));
+    }
+
+    #[test]
+    fn lookup_entries_keeps_read_entries() {
+        let map_fd = BpfMapFd::new(-1);
+        let mut entries = Vec::new();
+        assert!(lookup_entries(&map_fd, failing_lookup, &mut entries).is_err());
+        assert_eq!(entries.len(), MIN_BATCH_SIZE);
+    }
+
+    #[test]
+    fn lookup_chunk_returns_token() {
+        let map_fd = BpfMapFd::new(-1);
+        let mut keys = vec![MaybeUninit::uninit(); MIN_BATCH_SIZE];
+        let mut values = BatchBuffer::with_len(MIN_BATCH_SIZE);
+        let chunk = lookup_chunk(&map_fd, fake_lookup, None, &mut keys, &mut values).unwrap();
+        assert_eq!(chunk, (MIN_BATCH_SIZE, Some(MIN_BATCH_SIZE as u64)));
+        let chunk = lookup_chunk(&map_fd, fake_lookup, chunk.1, &mut keys, &mut values).unwrap();
+        assert_eq!(chunk, (ENTRIES as usize - MIN_BATCH_SIZE, None));
+    }
+}
@@ -1,3 +1,17 @@
 //! This module contains high-level userspace api built on top of libbpf safe wrapper api.
 
+pub mod cgroup;
+pub mod dispatcher;
+mod elf;
+pub mod flow_dissector;
+pub mod kprobe;
+pub mod link_monitor;
 pub mod maps;
+pub mod perf_event;
+pub mod reuseport;
+pub mod socket_filter;
+pub mod tc;
+pub mod tracepoint;
+pub mod uprobe;
+pub mod usdt;
+pub mod xdp;
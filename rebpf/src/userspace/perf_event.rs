@@ -0,0 +1,326 @@
+//! This module contains high-level api to attach `PERF_EVENT` programs to
+//! software or hardware perf events on every CPU, i.e. to sample the running
+//! tasks at a given frequency, and the perf_event plumbing used to attach bpf
+//! programs to kprobes, uprobes and tracepoints.
+
+use crate::{
+    error::{Error, Result},
+    libbpf::{BpfFd, BpfProgFd},
+    utils::*,
+};
+use libc;
+use std::{fs, mem, os::unix::io::RawFd, path::PathBuf};
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_TYPE_SOFTWARE: u32 = 1;
+pub(crate) const PERF_TYPE_TRACEPOINT: u32 = 2;
+
+const PERF_ATTR_FLAG_FREQ: u64 = 1 << 10;
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+const PERF_EVENT_IOC_SET_BPF: libc::c_ulong = 0x4004_2408;
+
+/// `struct perf_event_attr` from linux/perf_event.h (PERF_ATTR_SIZE_VER5).
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PerfEventAttr {
+    pub(crate) type_: u32,
+    pub(crate) size: u32,
+    pub(crate) config: u64,
+    /// `sample_period` or `sample_freq`, depending on the `freq` flag.
+    pub(crate) sample_period: u64,
+    pub(crate) sample_type: u64,
+    pub(crate) read_format: u64,
+    pub(crate) flags: u64,
+    pub(crate) wakeup_events: u32,
+    pub(crate) bp_type: u32,
+    pub(crate) config1: u64,
+    pub(crate) config2: u64,
+    pub(crate) branch_sample_type: u64,
+    pub(crate) sample_regs_user: u64,
+    pub(crate) sample_stack_user: u32,
+    pub(crate) clockid: i32,
+    pub(crate) sample_regs_intr: u64,
+    pub(crate) aux_watermark: u32,
+    pub(crate) sample_max_stack: u16,
+    pub(crate) reserved_2: u16,
+}
+
+impl PerfEventAttr {
+    pub(crate) fn new(type_: u32, config: u64) -> PerfEventAttr {
+        PerfEventAttr {
+            type_,
+            size: mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            ..Default::default()
+        }
+    }
+}
+
+#[named]
+pub(crate) fn perf_event_open(
+    attr: &PerfEventAttr,
+    pid: libc::pid_t,
+    cpu: i32,
+) -> Result<RawFd> {
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            attr as *const PerfEventAttr,
+            pid,
+            cpu,
+            -1,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(fd as RawFd)
+}
+
+/// Set `bpf_fd` as the program of the perf event `fd` and enable the event.
+#[named]
+pub(crate) fn perf_event_attach_bpf(fd: RawFd, bpf_fd: &BpfProgFd) -> Result<()> {
+    if unsafe { libc::ioctl(fd, PERF_EVENT_IOC_SET_BPF, bpf_fd.fd()) } < 0 {
+        return map_sys_error(function_name!());
+    }
+    if unsafe { libc::ioctl(fd, PERF_EVENT_IOC_ENABLE, 0) } < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(())
+}
+
+/// Returns the type of the dynamic PMU `pmu` (i.e. kprobe or uprobe),
+/// or None if the kernel doesn't provide it.
+pub(crate) fn pmu_type(pmu: &str) -> Option<u32> {
+    let path = format!("/sys/bus/event_source/devices/{}/type", pmu);
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Returns the bit of the config field that selects return probes for the
+/// dynamic PMU `pmu`, as described by its `format/retprobe` file ("config:0").
+#[named]
+pub(crate) fn pmu_retprobe_bit(pmu: &str) -> Result<u32> {
+    let path = format!("/sys/bus/event_source/devices/{}/format/retprobe", pmu);
+    let format = fs::read_to_string(path).map_err(map_io_error(function_name!()))?;
+    let format = format.trim();
+    if let Some(Ok(bit)) = format.strip_prefix("config:").map(str::parse) {
+        return Ok(bit);
+    }
+    Err(Error::Custom(format!("Invalid retprobe format: {}", format)))
+}
+
+/// Returns the mount point of tracefs.
+#[named]
+pub(crate) fn tracefs_path() -> Result<PathBuf> {
+    ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.join("events").is_dir())
+        .ok_or_else(|| {
+            Error::Sys(
+                function_name!().to_owned(),
+                std::io::Error::from_raw_os_error(libc::ENOENT),
+            )
+        })
+}
+
+/// Reads the id of the tracepoint `category`/`name` from tracefs.
+#[named]
+pub(crate) fn tracepoint_id(category: &str, name: &str) -> Result<u64> {
+    let path = tracefs_path()?
+        .join("events")
+        .join(category)
+        .join(name)
+        .join("id");
+    let id = fs::read_to_string(path).map_err(map_io_error(function_name!()))?;
+    id.trim()
+        .parse()
+        .map_err(|_| Error::Custom(format!("Invalid tracepoint id: {}", id.trim())))
+}
+
+/// A set of perf events with a bpf program attached, disabled and closed
+/// when dropped.
+pub struct PerfEventLink {
+    fds: Vec<RawFd>,
+}
+
+impl PerfEventLink {
+    pub(crate) fn new() -> PerfEventLink {
+        PerfEventLink { fds: Vec::new() }
+    }
+
+    /// Take ownership of the perf event `fd` and attach `bpf_fd` to it. The fd
+    /// is closed if the attachment fails, so the link only holds attached events.
+    pub(crate) fn push(&mut self, fd: RawFd, bpf_fd: &BpfProgFd) -> Result<()> {
+        if let Err(e) = perf_event_attach_bpf(fd, bpf_fd) {
+            unsafe {
+                libc::close(fd);
+            }
+            return Err(e);
+        }
+        self.fds.push(fd);
+        Ok(())
+    }
+
+    /// The number of perf events of the link.
+    pub fn len(&self) -> usize {
+        self.fds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fds.is_empty()
+    }
+
+    pub(crate) fn close(&mut self) {
+        for fd in self.fds.drain(..) {
+            unsafe {
+                libc::ioctl(fd, PERF_EVENT_IOC_DISABLE, 0);
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+impl Drop for PerfEventLink {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Software events, counted by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+#[allow(non_camel_case_types)]
+pub enum SoftwareEvent {
+    CPU_CLOCK = 0,
+    TASK_CLOCK = 1,
+    PAGE_FAULTS = 2,
+    CONTEXT_SWITCHES = 3,
+    CPU_MIGRATIONS = 4,
+    PAGE_FAULTS_MIN = 5,
+    PAGE_FAULTS_MAJ = 6,
+    ALIGNMENT_FAULTS = 7,
+    EMULATION_FAULTS = 8,
+}
+
+/// Generalized hardware events, counted by the PMU of the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+#[allow(non_camel_case_types)]
+pub enum HardwareEvent {
+    CPU_CYCLES = 0,
+    INSTRUCTIONS = 1,
+    CACHE_REFERENCES = 2,
+    CACHE_MISSES = 3,
+    BRANCH_INSTRUCTIONS = 4,
+    BRANCH_MISSES = 5,
+    BUS_CYCLES = 6,
+    STALLED_CYCLES_FRONTEND = 7,
+    STALLED_CYCLES_BACKEND = 8,
+    REF_CPU_CYCLES = 9,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerfEvent {
+    Software(SoftwareEvent),
+    Hardware(HardwareEvent),
+}
+
+/// When the program attached to a perf event runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplePolicy {
+    /// Every `n` occurrences of the event.
+    Period(u64),
+    /// `n` times per second, the kernel adjusting the period accordingly.
+    Frequency(u64),
+}
+
+impl PerfEvent {
+    fn attr(self, policy: SamplePolicy) -> PerfEventAttr {
+        let mut attr = match self {
+            PerfEvent::Software(event) => PerfEventAttr::new(PERF_TYPE_SOFTWARE, event as u64),
+            PerfEvent::Hardware(event) => PerfEventAttr::new(PERF_TYPE_HARDWARE, event as u64),
+        };
+        match policy {
+            SamplePolicy::Period(period) => attr.sample_period = period,
+            SamplePolicy::Frequency(freq) => {
+                attr.sample_period = freq;
+                attr.flags |= PERF_ATTR_FLAG_FREQ;
+            }
+        }
+        attr
+    }
+}
+
+/// Returns the online CPUs, read from /sys/devices/system/cpu/online.
+#[named]
+pub(crate) fn online_cpus() -> Result<Vec<i32>> {
+    let online = fs::read_to_string("/sys/devices/system/cpu/online")
+        .map_err(map_io_error(function_name!()))?;
+    parse_cpu_list(online.trim())
+        .ok_or_else(|| Error::Custom(format!("Invalid CPU list: {}", online.trim())))
+}
+
+/// Parse a CPU list such as `0-3,5,7-8`.
+fn parse_cpu_list(list: &str) -> Option<Vec<i32>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|r| !r.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let first: i32 = bounds.next()?.parse().ok()?;
+        let last: i32 = match bounds.next() {
+            Some(last) => last.parse().ok()?,
+            None => first,
+        };
+        cpus.extend(first..=last);
+    }
+    Some(cpus)
+}
+
+/// Attach the `PERF_EVENT` program `bpf_fd` to the perf event `event`,
+/// opened on every online CPU for every task.
+///
+/// Software events don't need the CPU PMU and are available in virtual
+/// machines, i.e. `SoftwareEvent::CPU_CLOCK` with `SamplePolicy::Frequency(99)`
+/// samples the running tasks of every CPU 99 times per second.
+pub fn attach_perf_event(
+    bpf_fd: &BpfProgFd,
+    event: PerfEvent,
+    policy: SamplePolicy,
+) -> Result<PerfEventLink> {
+    let attr = event.attr(policy);
+    let mut link = PerfEventLink::new();
+    for cpu in online_cpus()? {
+        let fd = perf_event_open(&attr, -1, cpu)?;
+        link.push(fd, bpf_fd)?;
+    }
+    Ok(link)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(parse_cpu_list("0"), Some(vec![0]));
+        assert_eq!(parse_cpu_list("0-3,5,7-8"), Some(vec![0, 1, 2, 3, 5, 7, 8]));
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-a"), None);
+    }
+
+    #[test]
+    fn sample_policy() {
+        let attr = PerfEvent::Software(SoftwareEvent::CPU_CLOCK).attr(SamplePolicy::Frequency(99));
+        assert_eq!(attr.type_, PERF_TYPE_SOFTWARE);
+        assert_eq!(attr.sample_period, 99);
+        assert_ne!(attr.flags & PERF_ATTR_FLAG_FREQ, 0);
+        let attr = PerfEvent::Hardware(HardwareEvent::INSTRUCTIONS).attr(SamplePolicy::Period(1000));
+        assert_eq!((attr.type_, attr.config), (PERF_TYPE_HARDWARE, 1));
+        assert_eq!(attr.flags & PERF_ATTR_FLAG_FREQ, 0);
+    }
+}
@@ -0,0 +1,115 @@
+//! This module contains high-level api to attach `SK_REUSEPORT` programs to
+//! groups of sockets bound to the same address with `SO_REUSEPORT`, and to
+//! create such groups of UDP sockets.
+//!
+//! The program selects, for each packet, the socket of the group receiving
+//! it from a `ReuseportSockArray` map (Linux 4.19+).
+
+use crate::{
+    error::Result,
+    libbpf::{BpfFd, BpfProgFd},
+    utils::*,
+};
+use libc;
+use std::{
+    mem,
+    net::{SocketAddr, UdpSocket},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+};
+
+const SO_ATTACH_REUSEPORT_EBPF: libc::c_int = 52;
+const SO_DETACH_REUSEPORT_BPF: libc::c_int = 68;
+
+fn setsockopt_int(fd: RawFd, option: libc::c_int, value: libc::c_int) -> libc::c_int {
+    unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    }
+}
+
+/// Attach the `SK_REUSEPORT` program `bpf_fd` to the reuseport group of
+/// `socket`, replacing the program already attached if any.
+#[named]
+pub fn attach<S: AsRawFd>(socket: &S, bpf_fd: &BpfProgFd) -> Result<()> {
+    if setsockopt_int(socket.as_raw_fd(), SO_ATTACH_REUSEPORT_EBPF, bpf_fd.fd()) < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(())
+}
+
+/// Detach the program attached to the reuseport group of `socket` (Linux 5.8+).
+#[named]
+pub fn detach<S: AsRawFd>(socket: &S) -> Result<()> {
+    if setsockopt_int(socket.as_raw_fd(), SO_DETACH_REUSEPORT_BPF, 0) < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(())
+}
+
+/// Create `count` UDP sockets bound to `addr` with `SO_REUSEPORT`, forming
+/// a reuseport group. If the port of `addr` is 0, the sockets are bound to the
+/// port picked by the kernel for the first one.
+#[named]
+pub fn udp_socket_group(addr: &SocketAddr, count: usize) -> Result<Vec<UdpSocket>> {
+    let mut addr = *addr;
+    let mut sockets = Vec::with_capacity(count);
+    for _ in 0..count {
+        let family = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = unsafe { libc::socket(family, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return map_sys_error(function_name!());
+        }
+        // The socket is closed on error once owned by the UdpSocket.
+        let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+        if setsockopt_int(fd, libc::SO_REUSEPORT, 1) < 0 {
+            return map_sys_error(function_name!());
+        }
+        let err = match addr {
+            SocketAddr::V4(addr) => {
+                let mut sin: libc::sockaddr_in = unsafe { mem::zeroed() };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = addr.port().to_be();
+                sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+                unsafe {
+                    libc::bind(
+                        fd,
+                        &sin as *const libc::sockaddr_in as *const libc::sockaddr,
+                        mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                    )
+                }
+            }
+            SocketAddr::V6(addr) => {
+                let mut sin6: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = addr.port().to_be();
+                sin6.sin6_addr.s6_addr = addr.ip().octets();
+                sin6.sin6_flowinfo = addr.flowinfo();
+                sin6.sin6_scope_id = addr.scope_id();
+                unsafe {
+                    libc::bind(
+                        fd,
+                        &sin6 as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                        mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                    )
+                }
+            }
+        };
+        if err < 0 {
+            return map_sys_error(function_name!());
+        }
+        if addr.port() == 0 {
+            let local_addr = socket.local_addr().map_err(map_io_error(function_name!()))?;
+            addr.set_port(local_addr.port());
+        }
+        sockets.push(socket);
+    }
+    Ok(sockets)
+}
@@ -0,0 +1,144 @@
+//! This module contains high-level api to attach `SOCKET_FILTER` programs to
+//! sockets, and to open raw `AF_PACKET` sockets bound to a network interface.
+//!
+//! A socket filter returns the number of bytes of the packet to keep, 0
+//! dropping the packet.
+
+use crate::{
+    error::Result,
+    interface::Interface,
+    libbpf::{BpfFd, BpfProgFd},
+    utils::*,
+};
+use libc;
+use std::{
+    mem,
+    os::unix::io::{AsRawFd, RawFd},
+};
+
+const SO_ATTACH_BPF: libc::c_int = 50;
+const SO_DETACH_BPF: libc::c_int = 27;
+
+const ETH_P_ALL: u16 = 0x0003;
+
+/// Attach the `SOCKET_FILTER` program `bpf_fd` to `socket`, replacing the
+/// filter already attached if any.
+#[named]
+pub fn attach<S: AsRawFd>(socket: &S, bpf_fd: &BpfProgFd) -> Result<()> {
+    let prog_fd: libc::c_int = bpf_fd.fd();
+    let err = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            SO_ATTACH_BPF,
+            &prog_fd as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if err < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(())
+}
+
+/// Detach the filter attached to `socket`.
+#[named]
+pub fn detach<S: AsRawFd>(socket: &S) -> Result<()> {
+    let unused: libc::c_int = 0;
+    let err = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            SO_DETACH_BPF,
+            &unused as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if err < 0 {
+        return map_sys_error(function_name!());
+    }
+    Ok(())
+}
+
+/// A raw `AF_PACKET` socket receiving every packet of an interface, with
+/// their ethernet header. Closed when dropped.
+pub struct PacketSocket {
+    fd: RawFd,
+}
+
+impl PacketSocket {
+    /// Open a packet socket bound to `interface`.
+    #[named]
+    pub fn open(interface: &Interface) -> Result<PacketSocket> {
+        let ifindex = interface.ifindex() as i32;
+        // The socket must be bound in the namespace of the interface.
+        let reply = interface.run_in_netns(move || {
+            let last_errno = || std::io::Error::last_os_error().raw_os_error();
+            let fd = unsafe {
+                libc::socket(
+                    libc::AF_PACKET,
+                    libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                    ETH_P_ALL.to_be() as libc::c_int,
+                )
+            };
+            if fd < 0 {
+                return Err(last_errno());
+            }
+            let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            addr.sll_family = libc::AF_PACKET as u16;
+            addr.sll_protocol = ETH_P_ALL.to_be();
+            addr.sll_ifindex = ifindex;
+            let err = unsafe {
+                libc::bind(
+                    fd,
+                    &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+                )
+            };
+            if err < 0 {
+                let errno = last_errno();
+                unsafe { libc::close(fd) };
+                return Err(errno);
+            }
+            Ok(fd)
+        })?;
+        match reply {
+            Ok(fd) => Ok(PacketSocket { fd }),
+            Err(errno) => map_errno_error(function_name!(), errno.unwrap_or(libc::EIO)),
+        }
+    }
+
+    /// Receive a packet into `buf`, blocking until one is available. Returns
+    /// the number of bytes copied into `buf` and the original length of the
+    /// packet, which is larger if the packet has been truncated.
+    #[named]
+    pub fn recv(&self, buf: &mut [u8]) -> Result<(usize, usize)> {
+        let len = unsafe {
+            libc::recv(
+                self.fd,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                libc::MSG_TRUNC,
+            )
+        };
+        if len < 0 {
+            return map_sys_error(function_name!());
+        }
+        let len = len as usize;
+        Ok((len.min(buf.len()), len))
+    }
+}
+
+impl AsRawFd for PacketSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! This module contains high-level api to attach `SCHED_CLS` programs to the
+//! ingress or egress path of a network interface, through a clsact qdisc.
+//!
+//! Programs are attached in direct-action mode: the `TcAction` they return
+//! is applied to the packet.
+
+use crate::{
+    error::{Error, Result},
+    interface::Interface,
+    libbpf::{self, BpfFd, BpfProgFd},
+    netlink::{self, NetlinkMessage, NetlinkSocket, TcMsg},
+};
+use libc;
+
+const TC_H_CLSACT: u32 = 0xFFFF_FFF1;
+const TC_H_MIN_INGRESS: u32 = 0xFFF2;
+const TC_H_MIN_EGRESS: u32 = 0xFFF3;
+const TC_H_MAJ_MASK: u32 = 0xFFFF_0000;
+const TC_H_MIN_MASK: u32 = 0x0000_FFFF;
+
+const TCA_KIND: u16 = 1;
+const TCA_OPTIONS: u16 = 2;
+
+const TCA_BPF_FD: u16 = 6;
+const TCA_BPF_NAME: u16 = 7;
+const TCA_BPF_FLAGS: u16 = 8;
+const TCA_BPF_ID: u16 = 11;
+const TCA_BPF_FLAG_ACT_DIRECT: u32 = 1 << 0;
+
+const ETH_P_ALL: u16 = 0x0003;
+
+fn tc_h_make(maj: u32, min: u32) -> u32 {
+    (maj & TC_H_MAJ_MASK) | (min & TC_H_MIN_MASK)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcAttachPoint {
+    Ingress,
+    Egress,
+}
+
+impl TcAttachPoint {
+    fn parent(self) -> u32 {
+        match self {
+            TcAttachPoint::Ingress => tc_h_make(TC_H_CLSACT, TC_H_MIN_INGRESS),
+            TcAttachPoint::Egress => tc_h_make(TC_H_CLSACT, TC_H_MIN_EGRESS),
+        }
+    }
+}
+
+/// A BPF classifier attached to a clsact qdisc, as reported by the kernel.
+#[derive(Debug, Clone)]
+pub struct TcFilter {
+    pub attach_point: TcAttachPoint,
+    pub handle: u32,
+    pub priority: u16,
+    pub prog_id: Option<u32>,
+    pub name: Option<String>,
+    pub direct_action: bool,
+}
+
+fn tc_msg(interface: &Interface, handle: u32, parent: u32, info: u32) -> TcMsg {
+    TcMsg {
+        tcm_family: libc::AF_UNSPEC as u8,
+        tcm_ifindex: interface.ifindex() as i32,
+        tcm_handle: handle,
+        tcm_parent: parent,
+        tcm_info: info,
+        ..Default::default()
+    }
+}
+
+fn filter_info(priority: u16) -> u32 {
+    tc_h_make((priority as u32) << 16, ETH_P_ALL.to_be() as u32)
+}
+
+/// Create the clsact qdisc on `interface`. It is not an error if it already exists.
+#[named]
+pub fn qdisc_add_clsact(interface: &Interface) -> Result<()> {
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let tcm = tc_msg(interface, tc_h_make(TC_H_CLSACT, 0), TC_H_CLSACT, 0);
+    let mut msg = NetlinkMessage::new(
+        netlink::RTM_NEWQDISC,
+        netlink::NLM_F_CREATE | netlink::NLM_F_EXCL,
+        &tcm,
+    );
+    msg.push_attr_str(TCA_KIND, "clsact");
+    match socket.request(function_name!(), msg) {
+        Err(Error::Sys(_, ref e)) if e.raw_os_error() == Some(libc::EEXIST) => Ok(()),
+        r => r,
+    }
+}
+
+/// Delete the clsact qdisc of `interface`, and with it every attached filter.
+#[named]
+pub fn qdisc_del_clsact(interface: &Interface) -> Result<()> {
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let tcm = tc_msg(interface, tc_h_make(TC_H_CLSACT, 0), TC_H_CLSACT, 0);
+    let mut msg = NetlinkMessage::new(netlink::RTM_DELQDISC, 0, &tcm);
+    msg.push_attr_str(TCA_KIND, "clsact");
+    socket.request(function_name!(), msg)
+}
+
+/// A BPF classifier attached by this process, detached when dropped.
+pub struct TcLink {
+    interface: Interface,
+    attach_point: TcAttachPoint,
+    handle: u32,
+    priority: u16,
+    /// Set once the filter is deleted, so that dropping the link doesn't delete it again.
+    detached: bool,
+}
+
+impl TcLink {
+    pub fn interface(&self) -> &Interface {
+        &self.interface
+    }
+
+    pub fn attach_point(&self) -> TcAttachPoint {
+        self.attach_point
+    }
+
+    pub fn handle(&self) -> u32 {
+        self.handle
+    }
+
+    pub fn priority(&self) -> u16 {
+        self.priority
+    }
+
+    /// Detach the classifier, reporting errors that dropping the link would ignore.
+    pub fn detach(mut self) -> Result<()> {
+        self.detached = true;
+        self.delete()
+    }
+
+    /// Drop the link without deleting the filter, i.e. once the filter is
+    /// already gone with its interface.
+    pub(crate) fn disarm(mut self) {
+        self.detached = true;
+    }
+
+    fn delete(&self) -> Result<()> {
+        delete_filter(&self.interface, self.attach_point, self.handle, self.priority)
+    }
+}
+
+impl Drop for TcLink {
+    fn drop(&mut self) {
+        if !self.detached {
+            let _ = self.delete();
+        }
+    }
+}
+
+/// Attach the `SCHED_CLS` program `bpf_fd` in direct-action mode to the
+/// `attach_point` of `interface`, creating the clsact qdisc if needed.
+///
+/// `handle` and `priority` identify the filter and must not be 0. Filters
+/// with a lower priority run first. It is an error if a filter with the same
+/// handle and priority already exists.
+#[named]
+pub fn attach(
+    interface: &Interface,
+    bpf_fd: &BpfProgFd,
+    attach_point: TcAttachPoint,
+    handle: u32,
+    priority: u16,
+) -> Result<TcLink> {
+    if handle == 0 || priority == 0 {
+        return Err(Error::Custom(
+            "TC filter handle and priority must not be 0".to_owned(),
+        ));
+    }
+    qdisc_add_clsact(interface)?;
+    let info = libbpf::bpf_obj_get_info_by_fd(bpf_fd)?;
+    let name = format!("{}:[{}]", info.name()?, info.id());
+
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let tcm = tc_msg(interface, handle, attach_point.parent(), filter_info(priority));
+    let mut msg = NetlinkMessage::new(
+        netlink::RTM_NEWTFILTER,
+        netlink::NLM_F_CREATE | netlink::NLM_F_EXCL,
+        &tcm,
+    );
+    msg.push_attr_str(TCA_KIND, "bpf");
+    msg.begin_nested(TCA_OPTIONS);
+    msg.push_attr_u32(TCA_BPF_FD, bpf_fd.fd() as u32);
+    msg.push_attr_str(TCA_BPF_NAME, &name);
+    msg.push_attr_u32(TCA_BPF_FLAGS, TCA_BPF_FLAG_ACT_DIRECT);
+    msg.end_nested();
+    socket.request(function_name!(), msg)?;
+
+    Ok(TcLink {
+        interface: interface.clone(),
+        attach_point,
+        handle,
+        priority,
+        detached: false,
+    })
+}
+
+/// Delete the BPF classifier identified by `handle` and `priority` from the
+/// `attach_point` of `interface`.
+#[named]
+pub fn delete_filter(
+    interface: &Interface,
+    attach_point: TcAttachPoint,
+    handle: u32,
+    priority: u16,
+) -> Result<()> {
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let tcm = tc_msg(interface, handle, attach_point.parent(), filter_info(priority));
+    let mut msg = NetlinkMessage::new(netlink::RTM_DELTFILTER, 0, &tcm);
+    msg.push_attr_str(TCA_KIND, "bpf");
+    socket.request(function_name!(), msg)
+}
+
+/// List the BPF classifiers attached to the `attach_point` of `interface`.
+#[named]
+pub fn list_filters(interface: &Interface, attach_point: TcAttachPoint) -> Result<Vec<TcFilter>> {
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let tcm = tc_msg(interface, 0, attach_point.parent(), 0);
+    let msg = NetlinkMessage::new(netlink::RTM_GETTFILTER, 0, &tcm);
+    let replies = socket.dump(function_name!(), msg)?;
+
+    let mut filters = Vec::new();
+    for reply in replies {
+        let (tcm, attrs) = match reply.split::<TcMsg>() {
+            Some(split) => split,
+            None => continue,
+        };
+        let mut filter = TcFilter {
+            attach_point,
+            handle: tcm.tcm_handle,
+            priority: (tcm.tcm_info >> 16) as u16,
+            prog_id: None,
+            name: None,
+            direct_action: false,
+        };
+        let mut is_bpf = false;
+        let mut has_options = false;
+        for (attr_type, payload) in attrs {
+            match attr_type {
+                TCA_KIND => is_bpf = netlink::attr_str(payload) == Some("bpf".to_owned()),
+                TCA_OPTIONS => {
+                    has_options = true;
+                    for (attr_type, payload) in netlink::parse_attrs(payload) {
+                        match attr_type {
+                            TCA_BPF_ID => filter.prog_id = netlink::attr_u32(payload),
+                            TCA_BPF_NAME => filter.name = netlink::attr_str(payload),
+                            TCA_BPF_FLAGS => {
+                                filter.direct_action = netlink::attr_u32(payload)
+                                    .is_some_and(|f| f & TCA_BPF_FLAG_ACT_DIRECT != 0)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        // The kernel also reports the filter chains themselves, without options.
+        if is_bpf && has_options {
+            filters.push(filter);
+        }
+    }
+
+    Ok(filters)
+}
@@ -0,0 +1,113 @@
+//! This module contains high-level api to attach `TRACEPOINT` programs to
+//! kernel tracepoints through perf events, and `RAW_TRACEPOINT` or
+//! `RAW_TRACEPOINT_WRITABLE` programs to raw tracepoints.
+//!
+//! Tracepoints are identified by their category and name, as listed in
+//! `available_events` of tracefs (i.e. `sched`, `sched_switch`). Raw
+//! tracepoints are identified by their name only.
+
+use crate::{
+    error::Result,
+    libbpf::{self, BpfProgFd},
+    userspace::perf_event::{self, PerfEventAttr, PerfEventLink},
+    utils::*,
+};
+use libc;
+use std::{fs, os::unix::io::RawFd};
+
+/// A tracepoint with a bpf program attached, detached when dropped.
+pub struct TracepointLink {
+    perf_link: PerfEventLink,
+    category: String,
+    name: String,
+}
+
+impl TracepointLink {
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Attach the `TRACEPOINT` program `bpf_fd` to the tracepoint `category`/`name`.
+pub fn attach_tracepoint(bpf_fd: &BpfProgFd, category: &str, name: &str) -> Result<TracepointLink> {
+    let id = perf_event::tracepoint_id(category, name)?;
+    let attr = PerfEventAttr::new(perf_event::PERF_TYPE_TRACEPOINT, id);
+    let fd = perf_event::perf_event_open(&attr, -1, 0)?;
+    let mut perf_link = PerfEventLink::new();
+    perf_link.push(fd, bpf_fd)?;
+    Ok(TracepointLink {
+        perf_link,
+        category: category.to_owned(),
+        name: name.to_owned(),
+    })
+}
+
+/// A raw tracepoint with a bpf program attached, detached when dropped.
+pub struct RawTracepointLink {
+    fd: RawFd,
+    name: String,
+}
+
+impl RawTracepointLink {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for RawTracepointLink {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Attach the `RAW_TRACEPOINT` or `RAW_TRACEPOINT_WRITABLE` program `bpf_fd`
+/// to the raw tracepoint `name` (Linux 4.17+).
+pub fn attach_raw_tracepoint(bpf_fd: &BpfProgFd, name: &str) -> Result<RawTracepointLink> {
+    let fd = libbpf::bpf_raw_tracepoint_open(name, bpf_fd)?;
+    Ok(RawTracepointLink {
+        fd,
+        name: name.to_owned(),
+    })
+}
+
+/// List the available tracepoints as `(category, name)` pairs. The names can
+/// also be used to attach raw tracepoints.
+#[named]
+pub fn list_tracepoints() -> Result<Vec<(String, String)>> {
+    let path = perf_event::tracefs_path()?.join("available_events");
+    let events = fs::read_to_string(path).map_err(map_io_error(function_name!()))?;
+    let mut tracepoints: Vec<_> = events.lines().filter_map(parse_event_line).collect();
+    tracepoints.sort();
+    Ok(tracepoints)
+}
+
+fn parse_event_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    let colon = line.find(':')?;
+    let (category, name) = (&line[..colon], &line[colon + 1..]);
+    if category.is_empty() || name.is_empty() {
+        return None;
+    }
+    Some((category.to_owned(), name.to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn event_lines() {
+        assert_eq!(
+            parse_event_line("sched:sched_switch"),
+            Some(("sched".to_owned(), "sched_switch".to_owned()))
+        );
+        assert_eq!(parse_event_line(""), None);
+        assert_eq!(parse_event_line("sched:"), None);
+    }
+}
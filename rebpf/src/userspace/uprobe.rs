@@ -0,0 +1,153 @@
+//! This module contains high-level api to attach `KPROBE` programs to
+//! functions of userspace binaries and libraries, as uprobes or uretprobes.
+//!
+//! Symbols are resolved parsing the ELF file of the binary and can be given
+//! either mangled or demangled, for Rust and C++ functions. Probes are created
+//! through the uprobe PMU (Linux 4.17+).
+
+use crate::{
+    error::{Error, Result},
+    libbpf::BpfProgFd,
+    userspace::{
+        elf::{self, Elf},
+        perf_event::{self, PerfEventAttr, PerfEventLink},
+    },
+    utils::*,
+};
+use std::path::{Path, PathBuf};
+
+/// A uprobe with a bpf program attached, removed when dropped.
+pub struct UprobeLink {
+    perf_link: PerfEventLink,
+    binary_path: PathBuf,
+    offset: u64,
+}
+
+impl UprobeLink {
+    pub fn binary_path(&self) -> &Path {
+        &self.binary_path
+    }
+
+    /// The offset of the probe in the binary file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// Returns the offset in the file `binary_path` of the function `symbol`.
+///
+/// `symbol` can be a raw symbol name or a demangled Rust (without hash) or C++
+/// (with or without parameters) name, i.e. `my_crate::module::function`.
+/// It is an error if the demangled name matches several functions.
+pub fn resolve_symbol(binary_path: &Path, symbol: &str) -> Result<u64> {
+    let elf = Elf::open(binary_path)?;
+    let mut matches: Vec<_> = elf
+        .symbols()
+        .into_iter()
+        .filter(|s| s.is_func && s.value != 0 && elf::symbol_matches(&s.name, symbol))
+        .map(|s| s.value)
+        .collect();
+    matches.sort();
+    matches.dedup();
+    let vaddr = match matches.as_slice() {
+        [vaddr] => *vaddr,
+        [] => {
+            return Err(Error::Custom(format!(
+                "Symbol {} not found in {}",
+                symbol,
+                binary_path.display()
+            )))
+        }
+        _ => {
+            return Err(Error::Custom(format!(
+                "Symbol {} is ambiguous in {}",
+                symbol,
+                binary_path.display()
+            )))
+        }
+    };
+    elf.vaddr_to_offset(vaddr).ok_or_else(|| {
+        Error::Custom(format!(
+            "Symbol {} is not mapped from {}",
+            symbol,
+            binary_path.display()
+        ))
+    })
+}
+
+/// Attach `bpf_fd` to the function `symbol` of `binary_path`, `offset` bytes
+/// after its entry. If `pid` is set, only the process `pid` is traced.
+pub fn attach_uprobe(
+    bpf_fd: &BpfProgFd,
+    binary_path: &Path,
+    symbol: &str,
+    offset: u64,
+    pid: Option<i32>,
+) -> Result<UprobeLink> {
+    let file_offset = resolve_symbol(binary_path, symbol)? + offset;
+    attach_uprobe_at(bpf_fd, binary_path, file_offset, false, pid)
+}
+
+/// Attach `bpf_fd` to the return of the function `symbol` of `binary_path`.
+/// If `pid` is set, only the process `pid` is traced.
+pub fn attach_uretprobe(
+    bpf_fd: &BpfProgFd,
+    binary_path: &Path,
+    symbol: &str,
+    pid: Option<i32>,
+) -> Result<UprobeLink> {
+    let file_offset = resolve_symbol(binary_path, symbol)?;
+    attach_uprobe_at(bpf_fd, binary_path, file_offset, true, pid)
+}
+
+/// Attach `bpf_fd` at the offset `file_offset` of the file `binary_path`.
+/// If `pid` is set, only the process `pid` is traced.
+pub fn attach_uprobe_at(
+    bpf_fd: &BpfProgFd,
+    binary_path: &Path,
+    file_offset: u64,
+    retprobe: bool,
+    pid: Option<i32>,
+) -> Result<UprobeLink> {
+    let fd = uprobe_perf_event_open(binary_path, file_offset, 0, retprobe, pid)?;
+    let mut perf_link = PerfEventLink::new();
+    perf_link.push(fd, bpf_fd)?;
+    Ok(UprobeLink {
+        perf_link,
+        binary_path: binary_path.to_owned(),
+        offset: file_offset,
+    })
+}
+
+/// Open a uprobe perf event. A non-zero `ref_ctr_offset` is the file offset of
+/// a semaphore incremented by the kernel while the probe is attached (Linux 4.20+).
+pub(crate) fn uprobe_perf_event_open(
+    binary_path: &Path,
+    file_offset: u64,
+    ref_ctr_offset: u64,
+    retprobe: bool,
+    pid: Option<i32>,
+) -> Result<std::os::unix::io::RawFd> {
+    let pmu_type = match perf_event::pmu_type("uprobe") {
+        Some(pmu_type) => pmu_type,
+        None => {
+            return Err(Error::Custom(
+                "The kernel doesn't provide the uprobe PMU".to_owned(),
+            ))
+        }
+    };
+    let path_cs = str_to_cstring(path_to_str(binary_path)?)?;
+    let mut attr = PerfEventAttr::new(pmu_type, 0);
+    if retprobe {
+        attr.config |= 1 << perf_event::pmu_retprobe_bit("uprobe")?;
+    }
+    attr.config |= ref_ctr_offset << 32;
+    attr.config1 = path_cs.as_ptr() as u64;
+    attr.config2 = file_offset;
+    // Probes on every process must be bound to a CPU, probes on a single process must not.
+    let (pid, cpu) = match pid {
+        Some(pid) => (pid, -1),
+        None => (-1, 0),
+    };
+    perf_event::perf_event_open(&attr, pid, cpu)
+}
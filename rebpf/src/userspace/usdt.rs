@@ -0,0 +1,359 @@
+//! This module contains high-level api to list the USDT probes (statically
+//! defined tracepoints, i.e. `DTRACE_PROBE` or `STAP_PROBE` macros) of a binary
+//! and to attach `KPROBE` programs to them, see [`usdt`] for an overview.
+//!
+//! The probes are read from the `.note.stapsdt` ELF section. Attaching a probe
+//! guarded by a semaphore makes the kernel increment it for as long as the
+//! probe is attached (Linux 4.20+).
+//!
+//! Only x86_64 argument specifications are supported: on other architectures
+//! only constant arguments can be decoded.
+//!
+//! [`usdt`]: ../../usdt/index.html
+
+use crate::{
+    error::{Error, Result},
+    libbpf::{self, BpfMapFd, BpfObject, BpfProgFd, BpfUpdateElemFlags},
+    map_layout::ScalarLayout,
+    usdt::{
+        UsdtArgSpec, UsdtSpec, USDT_ARG_CONST, USDT_ARG_REG, USDT_ARG_REG_DEREF, USDT_MAX_ARGS,
+        USDT_SPECS_MAP,
+    },
+    userspace::{elf::Elf, perf_event::PerfEventLink, uprobe},
+    utils::*,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const NT_STAPSDT: u32 = 3;
+
+/// A USDT probe of a binary.
+#[derive(Debug, Clone)]
+pub struct UsdtProbe {
+    pub provider: String,
+    pub name: String,
+    /// Virtual address of the probe.
+    pub pc: u64,
+    /// Offset of the probe in the binary file.
+    pub file_offset: u64,
+    /// Offset in the binary file of the semaphore guarding the probe, if any.
+    pub semaphore_offset: Option<u64>,
+    /// Argument specification, i.e. `-4@-4(%rbp) 8@%rax`.
+    pub args: String,
+}
+
+/// List the USDT probes of `binary_path`.
+pub fn list_probes(binary_path: &Path) -> Result<Vec<UsdtProbe>> {
+    elf_probes(&Elf::open(binary_path)?, binary_path)
+}
+
+/// List the USDT probes of `elf`, opened from `binary_path`.
+fn elf_probes(elf: &Elf, binary_path: &Path) -> Result<Vec<UsdtProbe>> {
+    // The addresses of the notes must be adjusted if the binary was prelinked.
+    let base_addr = elf.section(".stapsdt.base").map(|s| s.addr);
+    let mut probes = Vec::new();
+    for note in elf.notes(".note.stapsdt") {
+        if note.name != b"stapsdt" || note.type_ != NT_STAPSDT {
+            continue;
+        }
+        let parsed = (|| {
+            let mut pc = elf.read_u64(note.desc)?;
+            let base = elf.read_u64(note.desc.get(8..)?)?;
+            let mut semaphore = elf.read_u64(note.desc.get(16..)?)?;
+            if let Some(base_addr) = base_addr {
+                pc = pc.wrapping_add(base_addr).wrapping_sub(base);
+                if semaphore != 0 {
+                    semaphore = semaphore.wrapping_add(base_addr).wrapping_sub(base);
+                }
+            }
+            let mut strings = note.desc.get(24..)?.split(|&b| b == 0);
+            let provider = String::from_utf8_lossy(strings.next()?).into_owned();
+            let name = String::from_utf8_lossy(strings.next()?).into_owned();
+            let args = String::from_utf8_lossy(strings.next().unwrap_or(&[])).into_owned();
+            let semaphore_offset = if semaphore != 0 {
+                Some(elf.vaddr_to_offset(semaphore)?)
+            } else {
+                None
+            };
+            Some(UsdtProbe {
+                provider,
+                name,
+                pc,
+                file_offset: elf.vaddr_to_offset(pc)?,
+                semaphore_offset,
+                args,
+            })
+        })();
+        match parsed {
+            Some(probe) => probes.push(probe),
+            None => {
+                return Err(Error::Custom(format!(
+                    "Invalid USDT note in {}",
+                    binary_path.display()
+                )))
+            }
+        }
+    }
+    Ok(probes)
+}
+
+/// Offsets in `pt_regs` of the x86_64 registers, by name.
+#[cfg(target_arch = "x86_64")]
+const REGISTERS: &[(&[&str], u32)] = &[
+    (&["rip", "eip"], 128),
+    (&["rax", "eax", "ax", "al"], 80),
+    (&["rbx", "ebx", "bx", "bl"], 40),
+    (&["rcx", "ecx", "cx", "cl"], 88),
+    (&["rdx", "edx", "dx", "dl"], 96),
+    (&["rsi", "esi", "si", "sil"], 104),
+    (&["rdi", "edi", "di", "dil"], 112),
+    (&["rbp", "ebp", "bp", "bpl"], 32),
+    (&["rsp", "esp", "sp", "spl"], 152),
+    (&["r8", "r8d", "r8w", "r8b"], 72),
+    (&["r9", "r9d", "r9w", "r9b"], 64),
+    (&["r10", "r10d", "r10w", "r10b"], 56),
+    (&["r11", "r11d", "r11w", "r11b"], 48),
+    (&["r12", "r12d", "r12w", "r12b"], 24),
+    (&["r13", "r13d", "r13w", "r13b"], 16),
+    (&["r14", "r14d", "r14w", "r14b"], 8),
+    (&["r15", "r15d", "r15w", "r15b"], 0),
+];
+
+#[cfg(target_arch = "x86_64")]
+fn reg_offset(name: &str) -> Option<u32> {
+    REGISTERS
+        .iter()
+        .find(|(names, _)| names.contains(&name))
+        .map(|&(_, offset)| offset)
+}
+
+/// Register operands can't be decoded on other architectures.
+#[cfg(not(target_arch = "x86_64"))]
+fn reg_offset(_name: &str) -> Option<u32> {
+    None
+}
+
+/// Parse an argument of an argument specification:
+/// `[-]size@operand` where operand is `$imm`, `%reg`, `(%reg)` or `off(%reg)`.
+fn parse_arg(arg: &str) -> Option<UsdtArgSpec> {
+    let (size, signed, operand) = match arg.find('@') {
+        Some(at) => {
+            let size: i32 = arg[..at].parse().ok()?;
+            (size.unsigned_abs(), size < 0, &arg[at + 1..])
+        }
+        None => (8, false, arg),
+    };
+    match size {
+        1 | 2 | 4 | 8 => {}
+        _ => return None,
+    }
+    let mut spec = UsdtArgSpec {
+        size,
+        signed: signed as u32,
+        ..Default::default()
+    };
+    if let Some(value) = operand.strip_prefix('$') {
+        spec.kind = USDT_ARG_CONST;
+        spec.value = value.parse().ok()?;
+    } else if let Some(reg) = operand.strip_prefix('%') {
+        spec.kind = USDT_ARG_REG;
+        spec.reg_offset = reg_offset(reg)?;
+    } else if operand.ends_with(')') {
+        let open = operand.find('(')?;
+        let reg = operand[open + 1..operand.len() - 1].strip_prefix('%')?;
+        spec.kind = USDT_ARG_REG_DEREF;
+        // Addresses relative to rip aren't supported: rip is past the probe.
+        spec.reg_offset = match reg {
+            "rip" | "eip" => return None,
+            reg => reg_offset(reg)?,
+        };
+        spec.value = match &operand[..open] {
+            "" => 0,
+            offset => offset.parse().ok()?,
+        };
+    } else {
+        return None;
+    }
+    Some(spec)
+}
+
+/// Parse the argument specification of a USDT probe.
+pub fn parse_args(args: &str) -> Result<UsdtSpec> {
+    let mut spec = UsdtSpec::default();
+    for (i, arg) in args.split_whitespace().enumerate() {
+        if i >= USDT_MAX_ARGS {
+            return Err(Error::Custom(format!(
+                "Too many USDT arguments: {}",
+                args
+            )));
+        }
+        spec.args[i] = parse_arg(arg)
+            .ok_or_else(|| Error::Custom(format!("Unsupported USDT argument: {}", arg)))?;
+        spec.nr_args += 1;
+    }
+    Ok(spec)
+}
+
+/// Returns the address at which the file offset `file_offset` of
+/// `binary_path` is mapped in the process `pid`.
+#[named]
+fn runtime_address(binary_path: &Path, file_offset: u64, pid: i32) -> Result<u64> {
+    let path = fs::canonicalize(binary_path).map_err(map_io_error(function_name!()))?;
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid))
+        .map_err(map_io_error(function_name!()))?;
+    for line in maps.lines() {
+        // address perms offset dev inode pathname
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 6 || Path::new(fields[5]) != path {
+            continue;
+        }
+        let mut range = fields[0].split('-');
+        let parsed = (|| {
+            let start = u64::from_str_radix(range.next()?, 16).ok()?;
+            let end = u64::from_str_radix(range.next()?, 16).ok()?;
+            let offset = u64::from_str_radix(fields[2], 16).ok()?;
+            Some((start, end, offset))
+        })();
+        if let Some((start, end, offset)) = parsed {
+            if offset <= file_offset && file_offset - offset < end - start {
+                return Ok(start + file_offset - offset);
+            }
+        }
+    }
+    Err(Error::Custom(format!(
+        "{} is not mapped in process {}",
+        binary_path.display(),
+        pid
+    )))
+}
+
+/// USDT probes with a bpf program attached, removed when dropped.
+pub struct UsdtLink {
+    perf_link: PerfEventLink,
+    binary_path: PathBuf,
+    probes: Vec<UsdtProbe>,
+}
+
+impl UsdtLink {
+    pub fn binary_path(&self) -> &Path {
+        &self.binary_path
+    }
+
+    /// The probes attached by this link.
+    pub fn probes(&self) -> &[UsdtProbe] {
+        &self.probes
+    }
+}
+
+/// Attach `bpf_fd` to every USDT probe `provider`:`name` of `binary_path`
+/// (a probe can be expanded at several places) and store their argument
+/// specifications in the map declared by `usdt_specs_map!` in `bpf_object`.
+///
+/// If `pid` is set, only the process `pid` is traced. It must be set for
+/// shared libraries and position independent executables, whose probe
+/// addresses are only known once mapped in a process.
+pub fn attach_usdt(
+    bpf_fd: &BpfProgFd,
+    bpf_object: &BpfObject,
+    binary_path: &Path,
+    provider: &str,
+    name: &str,
+    pid: Option<i32>,
+) -> Result<UsdtLink> {
+    let elf = Elf::open(binary_path)?;
+    let probes: Vec<UsdtProbe> = elf_probes(&elf, binary_path)?
+        .into_iter()
+        .filter(|p| p.provider == provider && p.name == name)
+        .collect();
+    if probes.is_empty() {
+        return Err(Error::Custom(format!(
+            "USDT probe {}:{} not found in {}",
+            provider,
+            name,
+            binary_path.display()
+        )));
+    }
+    let specs_map: BpfMapFd<u64, UsdtSpec, ScalarLayout> =
+        libbpf::bpf_object__find_map_fd_by_name(bpf_object, USDT_SPECS_MAP)?;
+
+    let mut perf_link = PerfEventLink::new();
+    for probe in &probes {
+        let spec = parse_args(&probe.args)?;
+        let ip = match (elf.is_shared, pid) {
+            (false, _) => probe.pc,
+            (true, Some(pid)) => runtime_address(binary_path, probe.file_offset, pid)?,
+            (true, None) => {
+                return Err(Error::Custom(format!(
+                    "A pid is required to attach USDT probes of {}",
+                    binary_path.display()
+                )))
+            }
+        };
+        libbpf::bpf_map_update_elem(&specs_map, &ip, &spec, BpfUpdateElemFlags::ANY)?;
+        let fd = uprobe::uprobe_perf_event_open(
+            binary_path,
+            probe.file_offset,
+            probe.semaphore_offset.unwrap_or(0),
+            false,
+            pid,
+        )?;
+        perf_link.push(fd, bpf_fd)?;
+    }
+    Ok(UsdtLink {
+        perf_link,
+        binary_path: binary_path.to_owned(),
+        probes,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn parse_registers() {
+        let spec = parse_args("-4@%edi 8@%rax 1@%r8b").unwrap();
+        assert_eq!(spec.nr_args, 3);
+        assert_eq!(
+            spec.args[0],
+            UsdtArgSpec {
+                kind: USDT_ARG_REG,
+                reg_offset: 112,
+                value: 0,
+                size: 4,
+                signed: 1,
+            }
+        );
+        assert_eq!(spec.args[1].reg_offset, 80);
+        assert_eq!(spec.args[1].signed, 0);
+        assert_eq!(spec.args[2].reg_offset, 72);
+        assert_eq!(spec.args[2].size, 1);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn parse_memory_and_constants() {
+        let spec = parse_args("-4@-20(%rbp) 8@(%rsp) 4@$42 -8@$-1").unwrap();
+        assert_eq!(spec.nr_args, 4);
+        assert_eq!(spec.args[0].kind, USDT_ARG_REG_DEREF);
+        assert_eq!(spec.args[0].reg_offset, 32);
+        assert_eq!(spec.args[0].value, -20);
+        assert_eq!(spec.args[1].kind, USDT_ARG_REG_DEREF);
+        assert_eq!(spec.args[1].value, 0);
+        assert_eq!(spec.args[2].kind, USDT_ARG_CONST);
+        assert_eq!(spec.args[2].value, 42);
+        assert_eq!(spec.args[3].value, -1);
+        assert_eq!(parse_args("").unwrap().nr_args, 0);
+    }
+
+    #[test]
+    fn parse_unsupported() {
+        assert!(parse_args("8@8(%rip)").is_err());
+        assert!(parse_args("4@%ah").is_err());
+        assert!(parse_args("3@%eax").is_err());
+        assert!(parse_args("8@(%rax,%rbx,8)").is_err());
+    }
+}
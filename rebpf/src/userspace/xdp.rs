@@ -0,0 +1,355 @@
+//! This module contains high-level api to inspect and manage the XDP programs
+//! attached to a network interface, and to query the XDP capabilities of its driver.
+
+use crate::{
+    error::{Error, LibbpfError, Result},
+    interface::{self, Interface},
+    libbpf::{
+        self, BpfFd, BpfMapInfo, BpfObject, BpfProgFd, BpfProgInfo, BpfProgType, OwnedBpfFd,
+        RawBpfInsn, XdpFlags, XdpLinkInfo,
+    },
+    netlink::{self, IfInfoMsg, NetlinkMessage, NetlinkSocket},
+    utils::map_libbpf_sys_error,
+};
+use lazy_static::lazy_static;
+use libc;
+use std::path::Path;
+
+/// Kernel flag (since Linux 5.7) requesting the replacement of the program
+/// referred by IFLA_XDP_EXPECTED_FD.
+const XDP_FLAGS_REPLACE: u32 = 1 << 4;
+
+lazy_static! {
+    static ref REPLACE_SUPPORTED: bool = probe_replace();
+}
+
+/// Returns true if the kernel supports XDP_FLAGS_REPLACE, by asking to detach
+/// from `lo` a program which isn't attached to it: the kernel refuses with
+/// EEXIST if it supports the flag, or with EINVAL otherwise. `lo` is left
+/// untouched either way.
+fn probe_replace() -> bool {
+    let probe = || -> Result<()> {
+        let lo = interface::get_interface("lo")?;
+        let prog = libbpf::bpf_load_program(BpfProgType::XDP, &XDP_PASS_INSNS)?;
+        set_link_xdp_fd_replace(&lo, &prog, -1, XdpFlags::SKB_MODE)
+    };
+    match probe() {
+        Ok(()) => true,
+        Err(Error::Libbpf(_, LibbpfError::LibbpfSys(e))) => e == -libc::EEXIST,
+        Err(_) => false,
+    }
+}
+
+/// Returns the ids of the XDP programs attached to `interface`, for every mode.
+pub fn query(interface: &Interface) -> Result<XdpLinkInfo> {
+    libbpf::bpf_get_link_xdp_info(interface, XdpFlags::empty())
+}
+
+/// Returns a fd referring to the XDP program attached to `interface` in the mode
+/// selected by `xdp_flags`, or None if no program is attached. The fd is closed
+/// when dropped.
+pub fn attached_prog(
+    interface: &Interface,
+    xdp_flags: XdpFlags,
+) -> Result<Option<OwnedBpfFd<BpfProgFd>>> {
+    match query(interface)?.prog_id_by_mode(xdp_flags) {
+        Some(prog_id) => libbpf::bpf_prog_get_fd_by_id(prog_id).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Returns the info of the XDP program attached to `interface` in the mode
+/// selected by `xdp_flags`, together with the ids of the maps it uses.
+pub fn attached_prog_info(
+    interface: &Interface,
+    xdp_flags: XdpFlags,
+) -> Result<Option<(BpfProgInfo, Vec<u32>)>> {
+    match attached_prog(interface, xdp_flags)? {
+        Some(prog_fd) => {
+            let info = libbpf::bpf_obj_get_info_by_fd(&prog_fd)?;
+            let map_ids = libbpf::bpf_prog_get_map_ids(&prog_fd)?;
+            Ok(Some((info, map_ids)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Detach the XDP program attached to `interface` in the mode selected by
+/// `xdp_flags`, checking that its id is `expected_prog_id`.
+///
+/// On kernels supporting it (Linux 5.7 and later) the check is done by the
+/// kernel itself (XDP_FLAGS_REPLACE). On older kernels the attached program id
+/// is compared with `expected_prog_id` before detaching: a program attached by
+/// someone else between the two steps would be detached as well.
+///
+/// Returns `Error::UnexpectedXdpProg` without detaching anything if another
+/// program (or none) is attached.
+pub fn detach_prog(interface: &Interface, expected_prog_id: u32, xdp_flags: XdpFlags) -> Result<()> {
+    let xdp_flags = xdp_flags - XdpFlags::UPDATE_IF_NOEXIST;
+    let expected_prog = match libbpf::bpf_prog_get_fd_by_id(expected_prog_id) {
+        Ok(prog_fd) => prog_fd,
+        Err(Error::Sys(_, ref e)) if e.raw_os_error() == Some(libc::ENOENT) => {
+            // The expected program doesn't exist anymore, so it can't be attached.
+            let attached_id = query(interface)?.prog_id_by_mode(xdp_flags);
+            return Err(Error::UnexpectedXdpProg(expected_prog_id, attached_id));
+        }
+        Err(e) => return Err(e),
+    };
+    if !*REPLACE_SUPPORTED {
+        let attached_id = query(interface)?.prog_id_by_mode(xdp_flags);
+        if attached_id != Some(expected_prog_id) {
+            return Err(Error::UnexpectedXdpProg(expected_prog_id, attached_id));
+        }
+        return libbpf::bpf_set_link_xdp_fd(interface, None, xdp_flags);
+    }
+    match set_link_xdp_fd_replace(interface, &expected_prog, -1, xdp_flags) {
+        Err(Error::Libbpf(_, LibbpfError::LibbpfSys(e))) if e == -libc::EEXIST => {
+            let attached_id = query(interface)?.prog_id_by_mode(xdp_flags);
+            Err(Error::UnexpectedXdpProg(expected_prog_id, attached_id))
+        }
+        r => r,
+    }
+}
+
+/// Atomically replace the XDP program `old_prog` attached to `interface` by
+/// `new_prog`, so that no packet is processed without a program.
+///
+/// On kernels supporting it (Linux 5.7 and later) the replacement is done by
+/// the kernel itself (XDP_FLAGS_REPLACE). On older kernels the attached program
+/// id is compared with the one of `old_prog` before attaching `new_prog`: a
+/// concurrent update happening between the two steps can't be detected.
+///
+/// Returns `Error::UnexpectedXdpProg` if `old_prog` isn't the attached program
+/// anymore, i.e. if someone else replaced it in the meantime.
+pub fn replace_prog(
+    interface: &Interface,
+    old_prog: &BpfProgFd,
+    new_prog: &BpfProgFd,
+    xdp_flags: XdpFlags,
+) -> Result<()> {
+    let xdp_flags = xdp_flags - XdpFlags::UPDATE_IF_NOEXIST;
+    let old_id = libbpf::bpf_obj_get_info_by_fd(old_prog)?.id();
+    if !*REPLACE_SUPPORTED {
+        let attached_id = query(interface)?.prog_id_by_mode(xdp_flags);
+        if attached_id != Some(old_id) {
+            return Err(Error::UnexpectedXdpProg(old_id, attached_id));
+        }
+        return libbpf::bpf_set_link_xdp_fd(interface, Some(new_prog), xdp_flags);
+    }
+    match set_link_xdp_fd_replace(interface, old_prog, new_prog.fd(), xdp_flags) {
+        Err(Error::Libbpf(_, LibbpfError::LibbpfSys(e))) if e == -libc::EEXIST => {
+            let attached_id = query(interface)?.prog_id_by_mode(xdp_flags);
+            Err(Error::UnexpectedXdpProg(old_id, attached_id))
+        }
+        r => r,
+    }
+}
+
+#[named]
+fn set_link_xdp_fd_replace(
+    interface: &Interface,
+    old_prog: &BpfProgFd,
+    new_prog_fd: i32,
+    xdp_flags: XdpFlags,
+) -> Result<()> {
+    let mut socket = NetlinkSocket::new_in(interface.netns())?;
+    let ifinfo = IfInfoMsg {
+        ifi_family: libc::AF_UNSPEC as u8,
+        ifi_index: interface.ifindex() as i32,
+        ..Default::default()
+    };
+    let mut msg = NetlinkMessage::new(netlink::RTM_SETLINK, 0, &ifinfo);
+    msg.begin_nested(netlink::IFLA_XDP);
+    msg.push_attr_i32(netlink::IFLA_XDP_FD, new_prog_fd);
+    msg.push_attr_u32(netlink::IFLA_XDP_FLAGS, xdp_flags.bits() | XDP_FLAGS_REPLACE);
+    msg.push_attr_i32(netlink::IFLA_XDP_EXPECTED_FD, old_prog.fd());
+    msg.end_nested();
+    // Report the errors like bpf_set_link_xdp_fd, which is used when
+    // XDP_FLAGS_REPLACE isn't supported.
+    socket.request(function_name!(), msg).or_else(|e| match e {
+        Error::Sys(_, ref e) => {
+            map_libbpf_sys_error(function_name!(), -e.raw_os_error().unwrap_or(libc::EIO))
+        }
+        e => Err(e),
+    })
+}
+
+/// Open and load the bpf object at `file_path`, making the maps named in
+/// `reused_maps` point to already existing maps instead of creating new ones.
+///
+/// Combined with `replace_prog`, this allows to upgrade a program while keeping
+/// the state stored in its maps, i.e.:
+///
+/// ```no_run
+/// use rebpf::{interface, libbpf, userspace::{maps::{Array, Map}, xdp}};
+/// use std::path::Path;
+///
+/// # fn main() -> rebpf::error::Result<()> {
+/// let interface = interface::get_interface("eth0")?;
+/// let flags = libbpf::XdpFlags::SKB_MODE;
+/// let (old_obj, old_fd) = libbpf::bpf_prog_load(Path::new("v1.o"), libbpf::BpfProgType::XDP)?;
+/// libbpf::bpf_set_link_xdp_fd(&interface, Some(&old_fd), flags)?;
+///
+/// let counters = Array::<u64>::from_obj(&old_obj, "counters")?;
+/// let new_obj = xdp::load_reusing_maps(
+///     Path::new("v2.o"),
+///     libbpf::BpfProgType::XDP,
+///     &[("counters", counters.fd())],
+/// )?;
+/// let new_prog = libbpf::bpf_object__find_program_by_title(&new_obj, "xdp")?;
+/// xdp::replace_prog(&interface, &old_fd, &libbpf::bpf_program__fd(&new_prog)?, flags)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn load_reusing_maps(
+    file_path: &Path,
+    bpf_prog_type: BpfProgType,
+    reused_maps: &[(&str, &dyn BpfFd<BpfInfoType = BpfMapInfo>)],
+) -> Result<BpfObject> {
+    let bpf_object = libbpf::bpf_object__open(file_path)?;
+    for mut bpf_prog in &bpf_object {
+        libbpf::bpf_program__set_type(&mut bpf_prog, bpf_prog_type);
+    }
+    for (map_name, map_fd) in reused_maps {
+        let bpf_map = libbpf::bpf_object__find_map_by_name(&bpf_object, map_name)?;
+        libbpf::bpf_map__reuse_fd(&bpf_map, *map_fd)?;
+    }
+    libbpf::bpf_object__load(&bpf_object)?;
+
+    Ok(bpf_object)
+}
+
+/// Drivers supporting XDP in native (driver) mode in recent mainline Linux,
+/// used as a heuristic only: the support depends on the kernel version and
+/// may depend on the configuration of the device.
+const NATIVE_XDP_DRIVERS: &[&str] = &[
+    "bnxt_en",
+    "dpaa2-eth",
+    "ena",
+    "i40e",
+    "ice",
+    "igb",
+    "igc",
+    "ixgbe",
+    "ixgbevf",
+    "mlx4_en",
+    "mlx5_core",
+    "mvneta",
+    "mvpp2",
+    "netdevsim",
+    "nfp",
+    "nicvf",
+    "qede",
+    "sfc",
+    "stmmac",
+    "tun",
+    "veth",
+    "virtio_net",
+];
+
+/// Drivers supporting XDP offload (hardware mode), used as a heuristic only.
+const OFFLOAD_XDP_DRIVERS: &[&str] = &["netdevsim", "nfp"];
+
+/// A program returning `XDP_PASS`, used to probe the native mode.
+const XDP_PASS_INSNS: [RawBpfInsn; 2] = [
+    // r0 = XDP_PASS
+    RawBpfInsn {
+        code: 0xb7,
+        regs: 0,
+        off: 0,
+        imm: 2,
+    },
+    // exit
+    RawBpfInsn {
+        code: 0x95,
+        regs: 0,
+        off: 0,
+        imm: 0,
+    },
+];
+
+/// What an interface likely supports for XDP, and the programs attached to it.
+#[derive(Debug, Clone)]
+pub struct XdpCapabilities {
+    /// The driver name, None if the interface doesn't report it.
+    pub driver: Option<String>,
+    /// Whether the driver name is one of the drivers known to support the
+    /// native (driver) mode. Use `probe_native` for a reliable answer.
+    pub native_likely: bool,
+    /// Whether the driver name is one of the drivers known to support the
+    /// offload (hardware) mode.
+    pub offload_likely: bool,
+    /// The number of combined channels, None if the driver doesn't report them.
+    pub combined_channels: Option<u32>,
+    /// The id of the program attached in native mode, if any.
+    pub drv_prog_id: Option<u32>,
+    /// The id of the program attached in generic mode, if any.
+    pub skb_prog_id: Option<u32>,
+    /// The id of the program attached in offload mode, if any.
+    pub hw_prog_id: Option<u32>,
+}
+
+impl XdpCapabilities {
+    /// Returns the most efficient mode likely supported by the interface,
+    /// excluding the offload mode which only supports a subset of the bpf features.
+    pub fn best_mode(&self) -> XdpFlags {
+        if self.native_likely || self.drv_prog_id.is_some() {
+            XdpFlags::DRV_MODE
+        } else {
+            XdpFlags::SKB_MODE
+        }
+    }
+}
+
+/// Query the XDP capabilities of `interface`.
+///
+/// The kernel doesn't report whether a driver supports XDP: the support of
+/// the native and offload modes is guessed from the driver name.
+pub fn capabilities(interface: &Interface) -> Result<XdpCapabilities> {
+    let driver = match interface.driver_info() {
+        Ok(info) => Some(info.driver),
+        Err(Error::Sys(_, ref e)) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => None,
+        Err(e) => return Err(e),
+    };
+    let combined_channels = interface.channels().ok().map(|c| c.combined);
+    let link_info = query(interface)?;
+    let id = |id: u32| if id == 0 { None } else { Some(id) };
+    let supports = |drivers: &[&str]| {
+        driver
+            .as_ref()
+            .is_some_and(|d| drivers.contains(&d.as_str()))
+    };
+    Ok(XdpCapabilities {
+        native_likely: supports(NATIVE_XDP_DRIVERS),
+        offload_likely: supports(OFFLOAD_XDP_DRIVERS),
+        driver,
+        combined_channels,
+        drv_prog_id: id(link_info.drv_prog_id()),
+        skb_prog_id: id(link_info.skb_prog_id()),
+        hw_prog_id: id(link_info.hw_prog_id()),
+    })
+}
+
+/// Returns true if `interface` supports the native (driver) mode, by attaching
+/// then detaching a program passing every packet, unless a program is already
+/// attached in native mode.
+///
+/// Attaching a first XDP program makes some drivers reset their queues, which
+/// briefly interrupts the traffic. Fails with EEXIST if a program is attached
+/// in generic mode.
+pub fn probe_native(interface: &Interface) -> Result<bool> {
+    if query(interface)?.prog_id_by_mode(XdpFlags::DRV_MODE).is_some() {
+        return Ok(true);
+    }
+    let prog = libbpf::bpf_load_program(BpfProgType::XDP, &XDP_PASS_INSNS)?;
+    let xdp_flags = XdpFlags::UPDATE_IF_NOEXIST | XdpFlags::DRV_MODE;
+    match libbpf::bpf_set_link_xdp_fd(interface, Some(&prog), xdp_flags) {
+        Err(Error::Libbpf(_, LibbpfError::LibbpfSys(e))) if e == -libc::EOPNOTSUPP => Ok(false),
+        Err(e) => Err(e),
+        Ok(()) => {
+            let prog_id = libbpf::bpf_obj_get_info_by_fd(&prog)?.id();
+            detach_prog(interface, prog_id, XdpFlags::DRV_MODE)?;
+            Ok(true)
+        }
+    }
+}
@@ -61,3 +61,24 @@ pub(crate) fn map_libbpf_sys_error<T>(function_name: &str, e: i32) -> Result<T>
         LibbpfError::LibbpfSys(e),
     ))
 }
+
+#[cfg(feature = "userspace")]
+pub(crate) fn map_sys_error<T>(function_name: &str) -> Result<T> {
+    Err(Error::Sys(
+        function_name.to_owned(),
+        std::io::Error::last_os_error(),
+    ))
+}
+
+#[cfg(feature = "userspace")]
+pub(crate) fn map_errno_error<T>(function_name: &str, errno: i32) -> Result<T> {
+    Err(Error::Sys(
+        function_name.to_owned(),
+        std::io::Error::from_raw_os_error(errno),
+    ))
+}
+
+#[cfg(feature = "userspace")]
+pub(crate) fn map_io_error(function_name: &str) -> impl FnOnce(std::io::Error) -> Error + '_ {
+    move |e| Error::Sys(function_name.to_owned(), e)
+}